@@ -0,0 +1,611 @@
+// Unpacking of downloaded releases: zip, tar.gz and tar.xz, told apart by their contents.
+//
+// Archives are unpacked next to the destination and moved into place once everything is
+// written, a failed unpack leaves the previous contents of the destination alone. Entries can't
+// write outside of the destination: absolute and `..` paths, links pointing outside and writes
+// through links are refused.
+
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Default limit of the unpacked size, releases are a few hundred MB.
+pub static MAX_UNPACKED_SIZE: u64 = 4 << 30;
+/// Bytes of the archive read between progress reports.
+static PROGRESS_STEP: u64 = 1 << 20;
+
+#[cfg(unix)]
+const S_IFMT: u32 = 0o170000;
+#[cfg(unix)]
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    TarXz,
+}
+
+impl ArchiveFormat {
+    /// Looks at the magic bytes, the file name doesn't matter.
+    pub fn detect(path: &Path) -> Result<Self, String> {
+        let mut magic = [0u8; 6];
+        let mut file = std::fs::File::open(path)
+            .map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+        let len = read_up_to(&mut file, &mut magic)
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        match &magic[..len] {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => Ok(Self::Zip),
+            [0x1f, 0x8b, ..] => Ok(Self::TarGz),
+            [0xfd, b'7', b'z', b'X', b'Z', 0] => Ok(Self::TarXz),
+            _ => Err(format!(
+                "{} isn't a zip, tar.gz or tar.xz archive",
+                path.display()
+            )),
+        }
+    }
+}
+
+fn read_up_to(reader: &mut dyn Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+/// Unpacks `input_path` into `dest_dir`, replacing it. Fails if the unpacked files take more
+/// than `max_size` bytes. `progress` gets the archive bytes processed so far and the archive size.
+pub fn extract_to_dir(
+    input_path: &Path,
+    dest_dir: &Path,
+    max_size: u64,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<(), String> {
+    let format = ArchiveFormat::detect(input_path)?;
+    let file = std::fs::File::open(input_path)
+        .map_err(|e| format!("can't open {}: {}", input_path.display(), e))?;
+    let total = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    let result = extract_with(dest_dir, max_size, |out| match format {
+        ArchiveFormat::Zip => unpack_zip(file, total, out, progress),
+        ArchiveFormat::TarGz => {
+            let reader = Counting::new(file, total, progress);
+            unpack_tar(flate2::read::GzDecoder::new(reader), out)
+        }
+        ArchiveFormat::TarXz => {
+            let reader = Counting::new(file, total, progress);
+            unpack_tar(xz2::read::XzDecoder::new(reader), out)
+        }
+    });
+    if result.is_ok() {
+        progress(total, total);
+    }
+    result.map_err(|e| format!("can't unpack {}: {}", input_path.display(), e))
+}
+
+fn unpack_zip(
+    file: std::fs::File,
+    total: u64,
+    out: &mut Staging,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<(), String> {
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+    let mut done = 0;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
+        let name = file.name().to_owned();
+        let mode = file.unix_mode();
+        done += file.compressed_size();
+        #[cfg(unix)]
+        if mode.map_or(false, |mode| mode & S_IFMT == S_IFLNK) {
+            let mut target = String::new();
+            file.read_to_string(&mut target)
+                .map_err(|e| format!("can't read {}: {}", name, e))?;
+            out.symlink(&name, &target)?;
+            continue;
+        }
+        if file.is_dir() {
+            out.dir(&name)?;
+        } else {
+            out.file(&name, &mut file, mode)?;
+        }
+        progress(done.min(total), total);
+    }
+    Ok(())
+}
+
+fn unpack_tar(reader: impl Read, out: &mut Staging) -> Result<(), String> {
+    let mut archive = tar::Archive::new(reader);
+    let entries = archive.entries().map_err(|e| e.to_string())?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let link = entry
+            .link_name_bytes()
+            .map(|link| String::from_utf8_lossy(&link).into_owned());
+        let mode = entry.header().mode().ok();
+        match (entry.header().entry_type(), link) {
+            (tar::EntryType::Directory, _) => out.dir(&name)?,
+            (tar::EntryType::Regular | tar::EntryType::Continuous, _) => {
+                out.file(&name, &mut entry, mode)?
+            }
+            (tar::EntryType::Symlink, Some(target)) => out.symlink(&name, &target)?,
+            (tar::EntryType::Link, Some(target)) => out.hard_link(&name, &target, mode)?,
+            // pax headers, devices and such, nothing a release needs
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reports how much of the archive file was read.
+struct Counting<'a, R> {
+    inner: R,
+    done: u64,
+    reported: u64,
+    total: u64,
+    progress: &'a mut dyn FnMut(u64, u64),
+}
+
+impl<'a, R> Counting<'a, R> {
+    fn new(inner: R, total: u64, progress: &'a mut dyn FnMut(u64, u64)) -> Self {
+        Self {
+            inner,
+            done: 0,
+            reported: 0,
+            total,
+            progress,
+        }
+    }
+}
+
+impl<R: Read> Read for Counting<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.done += n as u64;
+        if self.done - self.reported >= PROGRESS_STEP {
+            self.reported = self.done;
+            (self.progress)(self.done, self.total);
+        }
+        Ok(n)
+    }
+}
+
+/// Runs `unpack` on a staging dir next to `dest_dir` and moves the result into place.
+fn extract_with(
+    dest_dir: &Path,
+    max_size: u64,
+    unpack: impl FnOnce(&mut Staging) -> Result<(), String>,
+) -> Result<(), String> {
+    let staging_dir = sibling(dest_dir, "partial")?;
+    let old_dir = sibling(dest_dir, "old")?;
+    for dir in [&staging_dir, &old_dir] {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)
+                .map_err(|e| format!("can't remove {}: {}", dir.display(), e))?;
+        }
+    }
+    std::fs::create_dir_all(&staging_dir)
+        .map_err(|e| format!("can't create {}: {}", staging_dir.display(), e))?;
+    let mut staging = Staging {
+        root: staging_dir.clone(),
+        size: 0,
+        max_size,
+        links: Vec::new(),
+    };
+    let result = unpack(&mut staging)
+        .and_then(|()| staging.check_links())
+        .and_then(|()| replace_dir(&staging_dir, &old_dir, dest_dir));
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(&staging_dir);
+    }
+    result
+}
+
+/// `<parent>/.<name>.<suffix>`, on the same file system so it can be renamed.
+fn sibling(dir: &Path, suffix: &str) -> Result<PathBuf, String> {
+    let name = dir
+        .file_name()
+        .ok_or_else(|| format!("invalid destination {}", dir.display()))?;
+    Ok(dir.with_file_name(format!(".{}.{}", name.to_string_lossy(), suffix)))
+}
+
+fn replace_dir(new_dir: &Path, old_dir: &Path, dest_dir: &Path) -> Result<(), String> {
+    let is_replacing = dest_dir.exists();
+    if is_replacing {
+        std::fs::rename(dest_dir, old_dir)
+            .map_err(|e| format!("can't move {}: {}", dest_dir.display(), e))?;
+    }
+    if let Err(e) = std::fs::rename(new_dir, dest_dir) {
+        if is_replacing {
+            let _ = std::fs::rename(old_dir, dest_dir);
+        }
+        return Err(format!("can't move into {}: {}", dest_dir.display(), e));
+    }
+    if is_replacing {
+        std::fs::remove_dir_all(old_dir)
+            .map_err(|e| format!("can't remove {}: {}", old_dir.display(), e))?;
+    }
+    Ok(())
+}
+
+/// The dir being unpacked into, it checks every entry before writing it.
+struct Staging {
+    root: PathBuf,
+    size: u64, // written so far
+    max_size: u64,
+    links: Vec<PathBuf>,
+}
+
+impl Staging {
+    fn dir(&mut self, name: &str) -> Result<(), String> {
+        let path = self.checked_path(name)?;
+        std::fs::create_dir_all(&path).map_err(|e| format!("can't create {}: {}", name, e))
+    }
+
+    fn file(&mut self, name: &str, reader: &mut dyn Read, mode: Option<u32>) -> Result<(), String> {
+        let path = self.checked_path(name)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("can't create {}: {}", name, e))?;
+        }
+        let mut out =
+            std::fs::File::create(&path).map_err(|e| format!("can't create {}: {}", name, e))?;
+        // one byte more than what's left, to notice the limit is crossed
+        let left = self.max_size - self.size;
+        let written = std::io::copy(&mut reader.take(left + 1), &mut out)
+            .map_err(|e| format!("can't write {}: {}", name, e))?;
+        if written > left {
+            return Err(format!(
+                "unpacked size is over the {} MB limit",
+                self.max_size >> 20
+            ));
+        }
+        self.size += written;
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = std::fs::Permissions::from_mode(mode & 0o777);
+            std::fs::set_permissions(&path, permissions)
+                .map_err(|e| format!("can't set permissions of {}: {}", name, e))?;
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn symlink(&mut self, name: &str, target: &str) -> Result<(), String> {
+        let path = self.checked_path(name)?;
+        let relative = relative_path(name)?;
+        let parent = relative.parent().unwrap_or_else(|| Path::new(""));
+        link_target(parent, target).map_err(|e| format!("link {} -> {}: {}", name, target, e))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("can't create {}: {}", name, e))?;
+        }
+        std::os::unix::fs::symlink(target, &path)
+            .map_err(|e| format!("can't create link {}: {}", name, e))?;
+        self.links.push(path);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn symlink(&mut self, name: &str, _target: &str) -> Result<(), String> {
+        Err(format!(
+            "can't create link {}, not supported on this OS",
+            name
+        ))
+    }
+
+    /// Hard links are unpacked as copies, `target` is a path in the archive.
+    fn hard_link(&mut self, name: &str, target: &str, mode: Option<u32>) -> Result<(), String> {
+        let source = self.checked_path(target)?;
+        let mut file = std::fs::File::open(&source)
+            .map_err(|e| format!("can't open {} for link {}: {}", target, name, e))?;
+        self.file(name, &mut file, mode)
+    }
+
+    /// Where `name` goes, refusing to write through links created by earlier entries.
+    fn checked_path(&self, name: &str) -> Result<PathBuf, String> {
+        let relative = relative_path(name)?;
+        let mut path = self.root.clone();
+        for component in relative.components() {
+            path.push(component);
+            if let Ok(meta) = std::fs::symlink_metadata(&path) {
+                if meta.file_type().is_symlink() {
+                    return Err(format!("{} would be written through a link", name));
+                }
+            }
+        }
+        Ok(path)
+    }
+
+    /// Links are checked one by one when they're created, a chain of them can still point
+    /// outside, so the final targets are checked as well.
+    fn check_links(&self) -> Result<(), String> {
+        let root = self
+            .root
+            .canonicalize()
+            .map_err(|e| format!("can't resolve {}: {}", self.root.display(), e))?;
+        for link in &self.links {
+            // a dangling link doesn't point anywhere
+            if let Ok(target) = link.canonicalize() {
+                if !target.starts_with(&root) {
+                    return Err(format!(
+                        "link {} points outside of the destination",
+                        link.strip_prefix(&self.root).unwrap_or(link).display()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The entry name as a relative path, both separators are accepted.
+fn relative_path(name: &str) -> Result<PathBuf, String> {
+    let mut path = PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return Err(format!("{:?} points outside of the destination", name)),
+            _ => {
+                let mut components = Path::new(part).components();
+                // drive letters and such
+                if !matches!(components.next(), Some(Component::Normal(_)))
+                    || components.next().is_some()
+                {
+                    return Err(format!("{:?} isn't a relative path", name));
+                }
+                path.push(part);
+            }
+        }
+    }
+    if name.starts_with(['/', '\\']) {
+        return Err(format!("{:?} isn't a relative path", name));
+    }
+    if path.as_os_str().is_empty() {
+        return Err(format!("{:?} is an empty path", name));
+    }
+    Ok(path)
+}
+
+/// Resolves a link `target` relative to the dir of the link, both relative to the destination.
+fn link_target(link_dir: &Path, target: &str) -> Result<PathBuf, String> {
+    if Path::new(target).is_absolute() || target.starts_with(['/', '\\']) {
+        return Err("absolute link target".to_owned());
+    }
+    let mut path = link_dir.to_owned();
+    for part in target.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if !path.pop() {
+                    return Err("link target is outside of the destination".to_owned());
+                }
+            }
+            _ => path.push(part),
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("archive_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        for (name, data) in files {
+            zip.start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    /// (name, link target, data), names are written as is, `..` included.
+    fn write_tar(entries: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (name, link, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_mode(0o755);
+            header.set_size(data.len() as u64);
+            match link {
+                Some(link) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_link_name(link).unwrap();
+                }
+                None => header.set_entry_type(tar::EntryType::Regular),
+            }
+            header.set_cksum();
+            tar.append(&header, *data).unwrap();
+        }
+        tar.into_inner().unwrap()
+    }
+
+    #[test]
+    fn tar_formats() {
+        let dir = temp_dir("tar");
+        let tar = write_tar(&[
+            ("DynamoRIO-Linux/bin64/drrun", None, b"drrun"),
+            ("DynamoRIO-Linux/lib64/libx.so", Some("libx.so.1"), b""),
+            ("DynamoRIO-Linux/lib64/libx.so.1", None, b"lib"),
+        ]);
+        // the names don't match the contents on purpose
+        let gz = dir.join("release.zip");
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&gz).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(&tar).unwrap();
+        encoder.finish().unwrap();
+        let xz = dir.join("release.bin");
+        let mut encoder = xz2::write::XzEncoder::new(std::fs::File::create(&xz).unwrap(), 6);
+        encoder.write_all(&tar).unwrap();
+        encoder.finish().unwrap();
+        let garbage = dir.join("garbage.tar.gz");
+        std::fs::write(&garbage, b"not an archive").unwrap();
+
+        let formats = [&gz, &xz, &garbage].map(|path| ArchiveFormat::detect(path));
+        let mut last_progress = (0, 0);
+        let gz_result = extract_to_dir(
+            &gz,
+            &dir.join("gz"),
+            MAX_UNPACKED_SIZE,
+            &mut |done, total| last_progress = (done, total),
+        );
+        let xz_result = extract_to_dir(&xz, &dir.join("xz"), MAX_UNPACKED_SIZE, &mut |_, _| {});
+        let drrun = dir
+            .join("xz")
+            .join("DynamoRIO-Linux")
+            .join("bin64")
+            .join("drrun");
+        let drrun_text = std::fs::read(&drrun);
+        let gz_size = std::fs::metadata(&gz).unwrap().len();
+        #[cfg(unix)]
+        let (drrun_mode, lib_text) = {
+            use std::os::unix::fs::PermissionsExt;
+            let lib = dir
+                .join("gz")
+                .join("DynamoRIO-Linux")
+                .join("lib64")
+                .join("libx.so");
+            (
+                std::fs::metadata(&drrun).unwrap().permissions().mode(),
+                std::fs::read(lib),
+            )
+        };
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(formats[0], Ok(ArchiveFormat::TarGz));
+        assert_eq!(formats[1], Ok(ArchiveFormat::TarXz));
+        assert!(formats[2].is_err());
+        gz_result.unwrap();
+        xz_result.unwrap();
+        assert_eq!(last_progress, (gz_size, gz_size));
+        assert_eq!(drrun_text.unwrap(), b"drrun");
+        #[cfg(unix)]
+        {
+            assert_eq!(drrun_mode & 0o777, 0o755);
+            assert_eq!(lib_text.unwrap(), b"lib");
+        }
+    }
+
+    #[test]
+    fn tar_escapes_are_refused() {
+        let dir = temp_dir("tar_escapes");
+        let mut results = Vec::new();
+        for entries in [
+            [("a.txt", None, &b"a"[..]), ("../evil.txt", None, b"evil")],
+            [("a.txt", None, b"a"), ("/tmp/evil.txt", None, b"evil")],
+            [("a.txt", None, b"a"), ("lib/up", Some("../../evil"), b"")],
+        ] {
+            let path = dir.join("evil.tar.gz");
+            let mut encoder = flate2::write::GzEncoder::new(
+                std::fs::File::create(&path).unwrap(),
+                flate2::Compression::default(),
+            );
+            encoder.write_all(&write_tar(&entries)).unwrap();
+            encoder.finish().unwrap();
+            results.push(extract_to_dir(
+                &path,
+                &dir.join("dest"),
+                MAX_UNPACKED_SIZE,
+                &mut |_, _| {},
+            ));
+        }
+        let is_dest_created = dir.join("dest").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(results.iter().all(|result| result.is_err()));
+        assert!(!is_dest_created);
+    }
+
+    #[test]
+    fn entry_paths() {
+        assert_eq!(
+            relative_path("a/./b\\c").unwrap(),
+            Path::new("a").join("b").join("c")
+        );
+        assert!(relative_path("a/../../b").is_err());
+        assert!(relative_path("/etc/passwd").is_err());
+        assert!(relative_path("\\evil").is_err());
+        assert!(relative_path("./").is_err());
+
+        assert_eq!(
+            link_target(Path::new("lib"), "../bin/x").unwrap(),
+            Path::new("bin").join("x")
+        );
+        assert!(link_target(Path::new("lib"), "../../x").is_err());
+        assert!(link_target(Path::new(""), "/usr/lib").is_err());
+    }
+
+    #[test]
+    fn replaces_dest_only_on_success() {
+        let dir = temp_dir("replace");
+        let dest = dir.join("dest");
+        std::fs::create_dir_all(&dest).unwrap();
+        std::fs::write(dest.join("old.txt"), b"old").unwrap();
+
+        let good = dir.join("good.zip");
+        write_zip(&good, &[("a/b.txt", b"new")]);
+        let evil = dir.join("evil.zip");
+        write_zip(&evil, &[("c.txt", b"c"), ("../evil.txt", b"evil")]);
+        let big = dir.join("big.zip");
+        write_zip(&big, &[("a.bin", &[0; 600]), ("b.bin", &[0; 600])]);
+
+        let evil_result = extract_to_dir(&evil, &dest, MAX_UNPACKED_SIZE, &mut |_, _| {});
+        let is_old_kept = dest.join("old.txt").exists();
+        let big_result = extract_to_dir(&big, &dest, 1000, &mut |_, _| {});
+        let good_result = extract_to_dir(&good, &dest, 1000, &mut |_, _| {});
+        let new_text = std::fs::read(dest.join("a").join("b.txt"));
+        let is_old_left = dest.join("old.txt").exists();
+        let leftovers = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(evil_result.unwrap_err().contains("outside"));
+        assert!(is_old_kept);
+        assert!(!dir.join("evil.txt").exists());
+        assert!(big_result.unwrap_err().contains("limit"));
+        good_result.unwrap();
+        assert_eq!(new_text.unwrap(), b"new");
+        assert!(!is_old_left);
+        assert_eq!(leftovers, 4); // 3 zips and dest, no staging dirs
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_stay_inside() {
+        let dir = temp_dir("links");
+        let mut staging = Staging {
+            root: dir.clone(),
+            size: 0,
+            max_size: MAX_UNPACKED_SIZE,
+            links: Vec::new(),
+        };
+        staging.symlink("lib/libx.so", "libx.so.1").unwrap();
+        let outside = staging.symlink("lib/up", "../..");
+        staging.symlink("here", ".").unwrap();
+        staging.symlink("parent", "here/..").unwrap();
+        let through = staging.file("here/x.txt", &mut &b"x"[..], None);
+        let over = staging.file("parent", &mut &b"x"[..], None);
+        let chained = staging.check_links();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(outside.is_err());
+        assert!(through.unwrap_err().contains("through a link"));
+        assert!(over.unwrap_err().contains("through a link"));
+        assert!(chained.unwrap_err().contains("parent"));
+    }
+}
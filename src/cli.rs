@@ -0,0 +1,710 @@
+use crate::cmdline::join_args;
+use crate::command::{validate_settings, DrRunCommand};
+use crate::download::{progress_text, DownloadError, DownloadEvent};
+use crate::export;
+use crate::install::{self, install_dr, installed_dr_dir, tools_dir, Catalogue, Installed};
+use crate::runner::{self, RunEvent, StopReason};
+use crate::settings::{config_path, DrToolInstrumentationMode, Profiles, Settings};
+use crate::symbol_path::{check_symbol_path, split_stores};
+use crate::symbols::{matching, module_symbols};
+use crate::symstore;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+static USAGE: &str =
+    "Usage: dr_symlogger_launcher --headless <COMMAND> [OPTIONS] [-- TARGET CMD...]
+
+Commands:
+  print-cmd            Print the generated drrun command line
+  check                Validate settings and report problems
+  run                  Run the generated command and exit with the target's exit code
+  export-chrome        Convert the trace to Chrome Trace Event JSON (chrome://tracing, Perfetto)
+  export-folded        Convert the trace to folded stacks (inferno, flamegraph.pl)
+  list-dr              List catalogued and installed DynamoRIO releases
+  install-dr           Install a DynamoRIO release into the tools dir, prints its DynamoRIO dir
+  install-tool         Download DrSymLogger into the --out dir (the current one), prints its path
+  list-pdbs            List the PDBs of a symbol store and check the module's one, exits with 1
+                       if it's missing
+  import-pdbs          Copy the PDBs of a folder and its subfolders into a symbol store
+  symbols              List the module's functions matching --grep from its PDB, exits with 1 if
+                       there are none
+
+Options (override values from the selected profile):
+  --config <FILE>      Profiles file, overrides $DR_SYMLOGGER_CONFIG and the per-user config dir
+  --profile <NAME>     Use the named profile instead of the one selected in the GUI
+  --dr-dir <DIR>       DynamoRIO directory
+  --dr-version <VER>   Use an installed DynamoRIO version, for install-dr the one to install (latest)
+  --tool <PATH>        DrSymLogger tool path
+  --symbol-path <PATH> Symbol path in _NT_SYMBOL_PATH syntax, the environment variable if empty
+  --module <NAME>      Module to instrument
+  --mode <exec|inst>   Instrumentation mode
+  --grep <SUBSTR>      Substring to match (case sensitive)
+  --redirect <FILE>    Redirect output to file, empty string disables it
+  --timeout <SECS>     Kill the target after SECS seconds (exit code 124), 0 disables it
+  -- <CMD>...          Target command line
+
+Install options:
+  --catalogue <URL>    Releases catalogue url or path, the built-in one by default
+  --tools-dir <DIR>    Managed tools directory, the per-user data dir by default
+  --sha256 <HEX>       Expected SHA-256 of the archive or the tool, overrides the catalogue's one
+
+Export options:
+  --trace <FILE>       Trace to convert, the redirect file by default
+  --out <FILE>         Output file, <trace>.json or <trace>.folded by default, - for stdout
+
+Symbol store options:
+  --store <DIR>        Store in the symstore layout, the first local one of the symbol path by default
+  --from <DIR>         Folder to import PDBs from
+";
+
+enum Command {
+    Help,
+    PrintCmd,
+    Check,
+    Run,
+    ExportChrome,
+    ExportFolded,
+    ListDr,
+    InstallDr,
+    InstallTool,
+    ListPdbs,
+    ImportPdbs,
+    Symbols,
+}
+
+struct Args {
+    command: Command,
+    dr_version: Option<String>,
+    sha256: Option<String>,
+    trace: Option<String>,
+    out: Option<String>,
+    store: Option<String>,
+    from: Option<String>,
+}
+
+/// Entry point for `--headless`, `args` are the ones following the flag. Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    // before the profiles file is loaded, it may be migrated or moved aside
+    if is_help(args) {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let mut settings = match profile_settings(args) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 2;
+        }
+    };
+    let args = match parse_args(args, &mut settings) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    let tools_dir = tools_dir(&settings.tools_dir);
+    match args.command {
+        Command::ListDr => return list_dr(&settings.catalogue_url, &tools_dir),
+        Command::InstallDr => {
+            return install(
+                &settings.catalogue_url,
+                &tools_dir,
+                args.dr_version.as_deref(),
+                args.sha256.as_deref(),
+            )
+        }
+        Command::InstallTool => {
+            return install_tool(
+                &settings.catalogue_url,
+                &tools_dir,
+                Path::new(args.out.as_deref().unwrap_or(".")),
+                args.sha256.as_deref(),
+            )
+        }
+        _ => {}
+    }
+    if let Some(version) = &args.dr_version {
+        match installed_dr_dir(&tools_dir, version) {
+            Ok(dir) => settings.dr_dir = dir.display().to_string(),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return 1;
+            }
+        }
+    }
+
+    let export: Option<(&str, export::ExportFn)> = match args.command {
+        Command::ExportChrome => Some(("json", export::export_chrome_trace)),
+        Command::ExportFolded => Some(("folded", export::export_folded)),
+        _ => None,
+    };
+    if let Some((ext, export_fn)) = export {
+        let trace = args.trace.unwrap_or(settings.redirect_to_file);
+        if trace.is_empty() {
+            eprintln!("Error: no trace file, use --trace or --redirect");
+            return 2;
+        }
+        let out = args.out.unwrap_or_else(|| format!("{}.{}", trace, ext));
+        return match export_fn(&trace, &out) {
+            Ok(()) => {
+                eprintln!("Exported {} -> {}", trace, out);
+                0
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                1
+            }
+        };
+    }
+
+    // symbol path messages are informational, keep them away from stdout
+    let mut log_text = String::new();
+    let symbol_path = check_symbol_path(&settings.symbol_path, &mut log_text);
+    eprint!("{}", log_text);
+
+    match args.command {
+        Command::ListPdbs | Command::ImportPdbs => {
+            let stores = split_stores(&symbol_path);
+            let Some(store) = args.store.or_else(|| stores.first().cloned()) else {
+                eprintln!("Error: no symbol store, use --store or --symbol-path");
+                return 2;
+            };
+            return match (args.command, args.from) {
+                (Command::ImportPdbs, Some(from)) => {
+                    import_pdbs(Path::new(&store), Path::new(&from))
+                }
+                (Command::ImportPdbs, None) => {
+                    eprintln!("Error: no folder to import from, use --from");
+                    2
+                }
+                _ => list_pdbs(&settings, Path::new(&store), &stores),
+            };
+        }
+        _ => {}
+    }
+
+    if let Command::Symbols = args.command {
+        return list_symbols(&settings, &symbol_path);
+    }
+
+    let errors = validate_settings(&settings);
+    match args.command {
+        Command::PrintCmd => {
+            let cmd = match DrRunCommand::new(&settings, &symbol_path) {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return 1;
+                }
+            };
+            for e in &errors {
+                eprintln!("Warning: {}", e);
+            }
+            println!("{}", cmd.to_command_line());
+            0
+        }
+        Command::Check => {
+            if errors.is_empty() {
+                println!("Settings are ok");
+                return 0;
+            }
+            for e in &errors {
+                println!("{}", e);
+            }
+            1
+        }
+        Command::Run => {
+            if !errors.is_empty() {
+                for e in &errors {
+                    eprintln!("{}", e);
+                }
+                return 1;
+            }
+            // validate_settings() has already rejected the invalid mode
+            let cmd = DrRunCommand::new(&settings, &symbol_path).unwrap();
+            eprintln!("Running: {}", cmd.to_command_line());
+            let run = match runner::start(&cmd, settings.timeout(), || {}) {
+                Ok(run) => run,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return 1;
+                }
+            };
+            let mut is_timed_out = false;
+            for event in run.rx {
+                match event {
+                    RunEvent::Stdout(line) => println!("{}", line),
+                    RunEvent::Stderr(line) => eprintln!("{}", line),
+                    RunEvent::Stopped(StopReason::Timeout) => {
+                        is_timed_out = true;
+                        eprintln!("Timed out after {} s", settings.timeout_secs);
+                    }
+                    RunEvent::Stopped(StopReason::User) => eprintln!("Stopped"),
+                    RunEvent::Exited(Ok(exit_code)) => {
+                        eprintln!("Exit code: {}", exit_code);
+                        if is_timed_out {
+                            return 124;
+                        }
+                        // killed by a signal has no code, report it as a failure
+                        return exit_code.code().unwrap_or(1);
+                    }
+                    RunEvent::Exited(Err(e)) => {
+                        eprintln!("Error: {}", e);
+                        return 1;
+                    }
+                }
+            }
+            1
+        }
+        Command::Help
+        | Command::ExportChrome
+        | Command::ExportFolded
+        | Command::ListDr
+        | Command::InstallDr
+        | Command::InstallTool
+        | Command::ListPdbs
+        | Command::ImportPdbs
+        | Command::Symbols => unreachable!(),
+    }
+}
+
+fn list_dr(catalogue_url: &str, tools_dir: &Path) -> i32 {
+    let catalogue = match Catalogue::load(catalogue_url) {
+        Ok(catalogue) => catalogue,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    let installed = match Installed::load(tools_dir) {
+        Ok(installed) => installed,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    for release in &catalogue.releases {
+        let status = match (
+            installed.dynamorio.get(&release.version),
+            release.host_asset(),
+        ) {
+            (Some(installed), _) => format!("installed: {}", installed.path),
+            (None, Some(asset)) => asset.url.clone(),
+            (None, None) => format!("no {} build", std::env::consts::OS),
+        };
+        println!("{:<12} {}", release.version, status);
+    }
+    for (version, installed) in &installed.dynamorio {
+        if catalogue.find(version).is_none() {
+            println!("{:<12} installed: {}", version, installed.path);
+        }
+    }
+    0
+}
+
+fn install(
+    catalogue_url: &str,
+    tools_dir: &Path,
+    version: Option<&str>,
+    sha256: Option<&str>,
+) -> i32 {
+    let catalogue = match Catalogue::load(catalogue_url) {
+        Ok(catalogue) => catalogue,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    let release = match version {
+        Some(version) => catalogue.find(version),
+        None => catalogue.latest(),
+    };
+    let Some(release) = release else {
+        eprintln!(
+            "Error: no DynamoRIO {} in the catalogue",
+            version.unwrap_or("release for this OS")
+        );
+        return 1;
+    };
+    eprintln!(
+        "Installing DynamoRIO {} into {} ...",
+        release.version,
+        tools_dir.display()
+    );
+    // Ctrl+C leaves the partial download behind, the next install-dr resumes it
+    let result = install_dr(
+        release,
+        tools_dir,
+        sha256,
+        &AtomicBool::new(false),
+        &mut print_event,
+    );
+    print_result(result)
+}
+
+/// Downloads DrSymLogger into `dest_dir`, prints the tool path.
+fn install_tool(
+    catalogue_url: &str,
+    tools_dir: &Path,
+    dest_dir: &Path,
+    sha256: Option<&str>,
+) -> i32 {
+    let catalogue = match Catalogue::load(catalogue_url) {
+        Ok(catalogue) => catalogue,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    eprintln!("Downloading DrSymLogger into {} ...", dest_dir.display());
+    let result = install::install_tool(
+        &catalogue,
+        tools_dir,
+        dest_dir,
+        sha256,
+        &AtomicBool::new(false),
+        &mut print_event,
+    );
+    print_result(result)
+}
+
+fn print_event(event: DownloadEvent) {
+    match event {
+        DownloadEvent::Progress { done, total } => {
+            eprint!("\r{}        ", progress_text(done, total))
+        }
+        DownloadEvent::Retrying {
+            attempt,
+            delay,
+            error,
+        } => eprintln!(
+            "\nDownload attempt {} failed: {}, retrying in {} s",
+            attempt,
+            error,
+            delay.as_secs()
+        ),
+        DownloadEvent::Unpacking { done, total } => {
+            eprint!("\rUnpacking {}        ", progress_text(done, Some(total)))
+        }
+        DownloadEvent::Warning(warning) => eprintln!("\nWarning: {}", warning),
+        DownloadEvent::Done(_) => {}
+    }
+}
+
+/// Prints the installed path to stdout.
+fn print_result(result: Result<PathBuf, DownloadError>) -> i32 {
+    eprintln!();
+    match result {
+        Ok(path) => {
+            println!("{}", path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+fn list_pdbs(settings: &Settings, store: &Path, stores: &[String]) -> i32 {
+    let pdbs = match symstore::list(store) {
+        Ok(pdbs) => pdbs,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    for pdb in &pdbs {
+        println!(
+            "{:<24} {:<34} {:>12} {}",
+            pdb.name,
+            pdb.key,
+            pdb.size,
+            pdb.path.display()
+        );
+    }
+    if settings.inst_module.is_empty() {
+        return 0;
+    }
+    // the module's PDB may be in any of the stores, not only the listed one
+    let mut stores = stores.to_vec();
+    stores.insert(0, store.display().to_string());
+    match symstore::module_pdb(settings, &stores) {
+        Ok(pdb) => {
+            let status = match &pdb.found {
+                Some(found) => format!("found: {}", found.display()),
+                None => "missing".to_owned(),
+            };
+            eprintln!(
+                "{}: {} {} {}",
+                pdb.module.display(),
+                pdb.id.name,
+                pdb.id.key(),
+                status
+            );
+            i32::from(pdb.found.is_none())
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+fn list_symbols(settings: &Settings, symbol_path: &str) -> i32 {
+    let stores = split_stores(symbol_path);
+    let symbols = match module_symbols(settings, &stores) {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    let mut count = 0;
+    for f in matching(&symbols.functions, &settings.substr) {
+        println!("{:08x} {:>8x} {}", f.rva, f.size, f.name);
+        count += 1;
+    }
+    eprintln!(
+        "{} of {} functions from {}",
+        count,
+        symbols.functions.len(),
+        symbols.debug_file.display()
+    );
+    if let Some(warning) = symbols.warning() {
+        eprintln!("Warning: {}", warning);
+    }
+    i32::from(count == 0)
+}
+
+fn import_pdbs(store: &Path, from: &Path) -> i32 {
+    let (imported, errors) = symstore::import_dir(store, from);
+    for path in &imported {
+        println!("{}", path.display());
+    }
+    for e in &errors {
+        eprintln!("Error: {}", e);
+    }
+    eprintln!("Imported {} PDBs into {}", imported.len(), store.display());
+    if errors.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+/// Settings of the `--profile` profile, or the selected one. It's looked up before the other
+/// options are parsed, so they override the profile no matter where `--profile` appears.
+fn profile_settings(args: &[String]) -> Result<Settings, String> {
+    let config = option_value(args, "--config")?;
+    let mut log_text = String::new();
+    let mut profiles = Profiles::load(&config_path(config), Path::new("."), &mut log_text);
+    eprint!("{}", log_text);
+    if let Some(name) = option_value(args, "--profile")? {
+        profiles.select(name)?;
+    }
+    Ok(profiles.current().clone())
+}
+
+fn is_help(args: &[String]) -> bool {
+    let mut args = args.iter().take_while(|a| a.as_str() != "--");
+    args.any(|a| a == "-h" || a == "--help")
+}
+
+/// Finds an option among the ones before `--`, the target command line isn't ours.
+pub fn option_value<'a>(args: &'a [String], name: &str) -> Result<Option<&'a str>, String> {
+    let mut options = args.iter().take_while(|a| a.as_str() != "--");
+    match options.position(|a| a == name) {
+        Some(pos) => args
+            .get(pos + 1)
+            .map(|v| Some(v.as_str()))
+            .ok_or_else(|| format!("missing value for {}", name)),
+        None => Ok(None),
+    }
+}
+
+fn parse_args(args: &[String], settings: &mut Settings) -> Result<Args, String> {
+    let mut command = None;
+    let mut dr_version = None;
+    let mut sha256 = None;
+    let mut trace = None;
+    let mut out = None;
+    let mut store = None;
+    let mut from = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| {
+            it.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {}", name))
+        };
+        match arg.as_str() {
+            "print-cmd" if command.is_none() => command = Some(Command::PrintCmd),
+            "check" if command.is_none() => command = Some(Command::Check),
+            "run" if command.is_none() => command = Some(Command::Run),
+            "export-chrome" if command.is_none() => command = Some(Command::ExportChrome),
+            "export-folded" if command.is_none() => command = Some(Command::ExportFolded),
+            "list-dr" if command.is_none() => command = Some(Command::ListDr),
+            "install-dr" if command.is_none() => command = Some(Command::InstallDr),
+            "install-tool" if command.is_none() => command = Some(Command::InstallTool),
+            "list-pdbs" if command.is_none() => command = Some(Command::ListPdbs),
+            "import-pdbs" if command.is_none() => command = Some(Command::ImportPdbs),
+            "symbols" if command.is_none() => command = Some(Command::Symbols),
+            // already applied by profile_settings()
+            "--config" | "--profile" => {
+                value(arg)?;
+            }
+            "--trace" => trace = Some(value(arg)?),
+            "--out" => out = Some(value(arg)?),
+            "--store" => store = Some(value(arg)?),
+            "--from" => from = Some(value(arg)?),
+            "--dr-dir" => settings.dr_dir = value(arg)?,
+            "--dr-version" => dr_version = Some(value(arg)?),
+            "--catalogue" => settings.catalogue_url = value(arg)?,
+            "--tools-dir" => settings.tools_dir = value(arg)?,
+            "--sha256" => sha256 = Some(value(arg)?),
+            "--tool" => settings.dr_tool_path = value(arg)?,
+            "--symbol-path" => settings.symbol_path = value(arg)?,
+            "--module" => settings.inst_module = value(arg)?,
+            "--mode" => {
+                settings.inst_mode = match value(arg)?.to_lowercase().as_str() {
+                    "exec" => DrToolInstrumentationMode::Exec,
+                    "inst" => DrToolInstrumentationMode::Inst,
+                    other => return Err(format!("unknown mode {:?}", other)),
+                }
+            }
+            "--grep" => settings.substr = value(arg)?,
+            "--redirect" => settings.redirect_to_file = value(arg)?,
+            "--timeout" => {
+                let secs = value(arg)?;
+                settings.timeout_secs = secs
+                    .parse()
+                    .map_err(|_| format!("invalid timeout {:?}", secs))?;
+            }
+            "--" => {
+                settings.cmd = join_args(&it.by_ref().collect::<Vec<_>>());
+            }
+            // help wins over whatever else is given
+            "-h" | "--help" => {
+                command = Some(Command::Help);
+                break;
+            }
+            other => return Err(format!("unexpected argument {:?}", other)),
+        }
+    }
+    let command = command.ok_or_else(|| "no command given".to_owned())?;
+    Ok(Args {
+        command,
+        dr_version,
+        sha256,
+        trace,
+        out,
+        store,
+        from,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmdline::split_command_line;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn options_before_separator() {
+        let args = strings(&["check", "--config", "p.json", "--", "app", "--profile", "x"]);
+        assert_eq!(option_value(&args, "--config"), Ok(Some("p.json")));
+        // the target's own options aren't ours
+        assert_eq!(option_value(&args, "--profile"), Ok(None));
+        let args = strings(&["check", "--profile"]);
+        assert!(option_value(&args, "--profile").is_err());
+        let mut settings = Settings::default();
+        assert!(parse_args(&args, &mut settings).is_err());
+    }
+
+    #[test]
+    fn options_override_profile() {
+        let dir = std::env::temp_dir().join(format!("cli_profile_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("profiles.json");
+        std::fs::write(
+            &config,
+            r#"{"version": 1, "current": "default", "profiles": {"default": {},
+                "parser": {"inst_module": "parser.dll", "substr": "parse"}}}"#,
+        )
+        .unwrap();
+        let config = config.display().to_string();
+        let args = strings(&[
+            "check",
+            "--module",
+            "x.dll",
+            "--config",
+            &config,
+            "--profile",
+            "parser",
+        ]);
+        let settings = profile_settings(&args);
+        let unknown = profile_settings(&strings(&["check", "--config", &config, "--profile", "y"]));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut settings = settings.unwrap();
+        assert_eq!(settings.inst_module, "parser.dll");
+        let parsed = parse_args(&args, &mut settings).unwrap();
+        assert!(matches!(parsed.command, Command::Check));
+        // --module comes before --profile but still wins
+        assert_eq!(settings.inst_module, "x.dll");
+        assert_eq!(settings.substr, "parse");
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn target_command_line() {
+        let mut settings = Settings::default();
+        let args = strings(&["run", "--", "app", "--timeout", "my file.txt"]);
+        parse_args(&args, &mut settings).unwrap();
+        assert_eq!(
+            settings.cmd,
+            join_args(&["app", "--timeout", "my file.txt"])
+        );
+        assert_eq!(settings.timeout_secs, 0);
+        let mut split = split_command_line(&settings.cmd).unwrap();
+        assert_eq!(split.pop().unwrap(), "my file.txt");
+    }
+
+    #[test]
+    fn help() {
+        let mut settings = Settings::default();
+        for args in [
+            &["--help", "check"][..],
+            &["run", "-h", "--bogus"],
+            &["--help"],
+        ] {
+            let args = strings(args);
+            assert!(is_help(&args));
+            let parsed = parse_args(&args, &mut settings).unwrap();
+            assert!(matches!(parsed.command, Command::Help));
+        }
+        // the target's own --help
+        let args = strings(&["run", "--", "app", "--help"]);
+        assert!(!is_help(&args));
+        let parsed = parse_args(&args, &mut settings).unwrap();
+        assert!(matches!(parsed.command, Command::Run));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut settings = Settings::default();
+        parse_args(&strings(&["run", "--timeout", "5"]), &mut settings).unwrap();
+        assert_eq!(settings.timeout(), Some(std::time::Duration::from_secs(5)));
+        for bad in ["x", "-1", ""] {
+            assert!(parse_args(&strings(&["run", "--timeout", bad]), &mut settings).is_err());
+        }
+        assert!(parse_args(&strings(&["run", "--timeout"]), &mut settings).is_err());
+        assert!(parse_args(&strings(&["--timeout", "5"]), &mut settings).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+// Target command line tokenization and re-quoting.
+//
+// Windows and POSIX disagree on what a backslash means, `C:\Program Files\x.exe` would be mangled
+// by shlex, so on Windows we follow the CommandLineToArgvW rules instead.
+
+/// Splits a command line into arguments using the current platform rules, `None` on unbalanced quotes.
+pub fn split_command_line(cmd: &str) -> Option<Vec<String>> {
+    if cfg!(windows) {
+        split_windows(cmd)
+    } else {
+        shlex::split(cmd)
+    }
+}
+
+/// Quotes a single argument so it survives `split_command_line` and the platform shell.
+pub fn quote_arg(arg: &str) -> String {
+    if cfg!(windows) {
+        quote_windows(arg)
+    } else {
+        shlex::quote(arg).into_owned()
+    }
+}
+
+/// Joins arguments back into a single command line.
+pub fn join_args<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|a| quote_arg(a.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn split_windows(cmd: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut cur = String::new();
+    let mut has_token = false;
+    let mut in_quotes = false;
+    let mut chars = cmd.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' if !in_quotes => {
+                if has_token {
+                    args.push(std::mem::take(&mut cur));
+                    has_token = false;
+                }
+            }
+            '\\' => {
+                // backslashes are literal unless they precede a quote
+                let mut n = 1;
+                while chars.peek() == Some(&'\\') {
+                    chars.next();
+                    n += 1;
+                }
+                if chars.peek() == Some(&'"') {
+                    cur.push_str(&"\\".repeat(n / 2));
+                    if n % 2 == 1 {
+                        chars.next();
+                        cur.push('"');
+                    }
+                } else {
+                    cur.push_str(&"\\".repeat(n));
+                }
+                has_token = true;
+            }
+            '"' => {
+                // "" inside quotes is a literal quote
+                if in_quotes && chars.peek() == Some(&'"') {
+                    chars.next();
+                    cur.push('"');
+                } else {
+                    in_quotes = !in_quotes;
+                }
+                has_token = true;
+            }
+            _ => {
+                cur.push(c);
+                has_token = true;
+            }
+        }
+    }
+    if in_quotes {
+        return None;
+    }
+    if has_token {
+        args.push(cur);
+    }
+    Some(args)
+}
+
+pub fn quote_windows(arg: &str) -> String {
+    // cmd.exe metacharacters are quoted too, so the line can be passed through `cmd /c`
+    let needs_quotes = arg.is_empty()
+        || arg.chars().any(|c| {
+            matches!(
+                c,
+                ' ' | '\t' | '"' | '&' | '|' | '<' | '>' | '^' | '(' | ')'
+            )
+        });
+    if !needs_quotes {
+        return arg.to_owned();
+    }
+    let mut out = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                out.push_str(&"\\".repeat(backslashes * 2 + 1));
+                out.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                out.push_str(&"\\".repeat(backslashes));
+                out.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // trailing backslashes would escape the closing quote
+    out.push_str(&"\\".repeat(backslashes * 2));
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_plain() {
+        assert_eq!(
+            split_windows("cmd.exe  /c\tcmd.bat").unwrap(),
+            vec!["cmd.exe", "/c", "cmd.bat"]
+        );
+    }
+
+    #[test]
+    fn windows_quoted_path() {
+        assert_eq!(
+            split_windows(r#""C:\Program Files\app\app.exe" -f "a b.txt" C:\x\"#).unwrap(),
+            vec![r"C:\Program Files\app\app.exe", "-f", "a b.txt", r"C:\x\"]
+        );
+    }
+
+    #[test]
+    fn windows_escaped_quotes() {
+        assert_eq!(
+            split_windows(r#"a\"b "c\\" "d""e" """#).unwrap(),
+            vec![r#"a"b"#, r"c\", r#"d"e"#, ""]
+        );
+    }
+
+    #[test]
+    fn windows_unbalanced() {
+        assert_eq!(split_windows(r#"app.exe "unterminated"#), None);
+    }
+
+    #[test]
+    fn windows_quote_round_trip() {
+        let args = vec![
+            r"C:\Program Files\app.exe",
+            "plain",
+            "",
+            r#"say "hi""#,
+            r"trailing\ ",
+            r"dir with\",
+            "a&b",
+        ];
+        let line = args
+            .iter()
+            .map(|a| quote_windows(a))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(split_windows(&line).unwrap(), args);
+    }
+
+    #[test]
+    fn windows_quote_only_when_needed() {
+        assert_eq!(
+            quote_windows(r"C:\tools\DrSymLogger.dll"),
+            r"C:\tools\DrSymLogger.dll"
+        );
+        assert_eq!(quote_windows("a b"), r#""a b""#);
+    }
+
+    #[test]
+    fn unix_round_trip() {
+        let args = vec!["/opt/my app/bin", "it's", "", "plain"];
+        let line = args
+            .iter()
+            .map(|a| shlex::quote(a).into_owned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(shlex::split(&line).unwrap(), args);
+    }
+}
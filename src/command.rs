@@ -0,0 +1,311 @@
+use crate::cmdline::{quote_arg, split_command_line};
+use crate::settings::{DrToolInstrumentationMode, Settings};
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(windows)]
+pub static DRRUN_NAME: &str = "drrun.exe";
+#[cfg(not(windows))]
+pub static DRRUN_NAME: &str = "drrun";
+
+#[cfg(windows)]
+pub static DR_TOOL_NAME: &str = "DrSymLogger.dll";
+#[cfg(not(windows))]
+pub static DR_TOOL_NAME: &str = "libDrSymLogger.so";
+
+/// Structured `drrun` invocation built from [`Settings`], independent of how it's going to be run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrRunCommand {
+    pub program: String,
+    pub env: Vec<(String, String)>,
+    pub dr_args: Vec<String>,
+    pub tool_args: Vec<String>,
+    pub target_args: Vec<String>,
+    pub redirect: Option<String>,
+}
+
+impl DrRunCommand {
+    pub fn new(settings: &Settings, symbol_path: &str) -> Result<Self, String> {
+        // --printSymsExec vs --printSymsInst
+        let str_mode = match settings.inst_mode {
+            DrToolInstrumentationMode::Exec => "--printSymsExec",
+            DrToolInstrumentationMode::Inst => "--printSymsInst",
+            DrToolInstrumentationMode::Invalid => {
+                return Err("Invalid instrumentation mode".to_owned())
+            }
+        };
+
+        let mut tool_args = vec![
+            str_mode.to_owned(),
+            "--printSymsExecConsole".to_owned(),
+            "--printSymsModule".to_owned(),
+            settings.inst_module.clone(),
+        ];
+        if !settings.substr.is_empty() {
+            tool_args.push("--printSymsGrep".to_owned());
+            tool_args.push(settings.substr.clone());
+        }
+
+        let target_args = split_command_line(&settings.cmd)
+            .ok_or_else(|| "Unbalanced quotes in command line".to_owned())?;
+
+        let redirect = if settings.redirect_to_file.is_empty() {
+            None
+        } else {
+            Some(settings.redirect_to_file.clone())
+        };
+
+        Ok(Self {
+            program: Path::new(&settings.dr_dir)
+                .join("bin64")
+                .join(DRRUN_NAME)
+                .display()
+                .to_string(),
+            env: vec![("_NT_SYMBOL_PATH".to_owned(), symbol_path.to_owned())],
+            dr_args: vec!["-c".to_owned(), settings.dr_tool_path.clone()],
+            tool_args,
+            target_args,
+            redirect,
+        })
+    }
+
+    /// Full argument vector passed to `program`: DR args, tool args, `--` and the target.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = self.dr_args.clone();
+        args.extend(self.tool_args.iter().cloned());
+        args.push("--".to_owned());
+        args.extend(self.target_args.iter().cloned());
+        args
+    }
+
+    /// Shell command line, the same one the GUI shows and copies to clipboard.
+    /// Every argument is re-quoted, so paths with spaces survive the shell.
+    pub fn to_command_line(&self) -> String {
+        let mut cmd = String::new();
+        for (name, value) in &self.env {
+            if cfg!(windows) {
+                // quoting the whole assignment keeps trailing spaces out of the value
+                cmd.push_str(&format!("set \"{}={}\" && ", name, value));
+            } else {
+                cmd.push_str(&format!("{}={} ", name, quote_arg(value)));
+            }
+        }
+        cmd.push_str(&quote_arg(&self.program));
+        for arg in self.args() {
+            cmd.push(' ');
+            cmd.push_str(&quote_arg(&arg));
+        }
+        if let Some(redirect) = &self.redirect {
+            cmd.push_str(format!(" > {} 2>&1", quote_arg(redirect)).as_str());
+        }
+        cmd
+    }
+
+    /// Process builder for `drrun` with the arguments and environment set, stdio untouched.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command
+            .args(self.args())
+            .envs(self.env.iter().map(|(k, v)| (k, v)));
+        command
+    }
+}
+
+/// Returns a list of problems preventing the command from being run, empty if all is fine.
+pub fn validate_settings(settings: &Settings) -> Vec<String> {
+    let mut errors = Vec::new();
+    if !check_dr_dir(&settings.dr_dir) {
+        errors.push(format!("Invalid DR dir: {:?}", settings.dr_dir));
+    }
+    if !check_dr_tool_path(&settings.dr_tool_path) {
+        errors.push(format!("Invalid DR tool path: {:?}", settings.dr_tool_path));
+    }
+    if settings.inst_module.is_empty() {
+        errors.push("Module can't be empty".to_owned());
+    }
+    if settings.inst_mode == DrToolInstrumentationMode::Invalid {
+        errors.push("Invalid instrumentation mode".to_owned());
+    }
+    if settings.cmd.is_empty() {
+        errors.push("Command line can't be empty".to_owned());
+    }
+    if split_command_line(&settings.cmd).is_none() {
+        errors.push("Unbalanced quotes in command line".to_owned());
+    }
+    errors
+}
+
+pub fn check_dr_dir(dr_dir: &str) -> bool {
+    let dr_dir = Path::new(dr_dir);
+    if !dr_dir.exists() {
+        return false;
+    }
+    let dr_dir = dr_dir.join("bin64");
+    if !dr_dir.exists() {
+        return false;
+    }
+    let dr_dir = dr_dir.join(DRRUN_NAME);
+    if !dr_dir.exists() {
+        return false;
+    }
+    true
+}
+
+pub fn check_dr_tool_path(dr_tool_path: &str) -> bool {
+    // check if path exists, and the file name name is DrSymLogger.dll (libDrSymLogger.so)
+    let dr_tool_path = Path::new(dr_tool_path);
+    if !dr_tool_path.is_file() {
+        return false;
+    }
+    dr_tool_path.file_name() == Some(DR_TOOL_NAME.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            dr_dir: "C:\\DynamoRIO".to_owned(),
+            dr_tool_path: "C:\\tools\\DrSymLogger.dll".to_owned(),
+            inst_module: "cmd.exe".to_owned(),
+            inst_mode: DrToolInstrumentationMode::Exec,
+            substr: "".to_owned(),
+            redirect_to_file: "".to_owned(),
+            cmd: "cmd.exe /c cmd.bat".to_owned(),
+            timeout_secs: 0,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn exec_mode() {
+        let cmd = DrRunCommand::new(&settings(), "c:\\symbols").unwrap();
+        assert_eq!(
+            Path::new(&cmd.program),
+            Path::new("C:\\DynamoRIO").join("bin64").join(DRRUN_NAME)
+        );
+        assert_eq!(
+            cmd.env,
+            vec![("_NT_SYMBOL_PATH".to_owned(), "c:\\symbols".to_owned())]
+        );
+        assert_eq!(cmd.dr_args, vec!["-c", "C:\\tools\\DrSymLogger.dll"]);
+        assert_eq!(
+            cmd.tool_args,
+            vec![
+                "--printSymsExec",
+                "--printSymsExecConsole",
+                "--printSymsModule",
+                "cmd.exe"
+            ]
+        );
+        assert_eq!(cmd.target_args, vec!["cmd.exe", "/c", "cmd.bat"]);
+        assert_eq!(cmd.redirect, None);
+    }
+
+    #[test]
+    fn inst_mode() {
+        let mut s = settings();
+        s.inst_mode = DrToolInstrumentationMode::Inst;
+        let cmd = DrRunCommand::new(&s, "").unwrap();
+        assert_eq!(cmd.tool_args[0], "--printSymsInst");
+    }
+
+    #[test]
+    fn invalid_mode() {
+        let mut s = settings();
+        s.inst_mode = DrToolInstrumentationMode::Invalid;
+        assert!(DrRunCommand::new(&s, "").is_err());
+    }
+
+    #[test]
+    fn grep_substring() {
+        let mut s = settings();
+        s.substr = "Parse".to_owned();
+        let cmd = DrRunCommand::new(&s, "").unwrap();
+        assert_eq!(cmd.tool_args[4..], ["--printSymsGrep", "Parse"]);
+    }
+
+    #[test]
+    fn redirect() {
+        let mut s = settings();
+        s.redirect_to_file = "log.txt".to_owned();
+        let cmd = DrRunCommand::new(&s, "").unwrap();
+        assert_eq!(cmd.redirect.as_deref(), Some("log.txt"));
+        assert!(cmd.to_command_line().ends_with(" > log.txt 2>&1"));
+    }
+
+    #[test]
+    fn args_order() {
+        let cmd = DrRunCommand::new(&settings(), "").unwrap();
+        assert_eq!(
+            cmd.args(),
+            vec![
+                "-c",
+                "C:\\tools\\DrSymLogger.dll",
+                "--printSymsExec",
+                "--printSymsExecConsole",
+                "--printSymsModule",
+                "cmd.exe",
+                "--",
+                "cmd.exe",
+                "/c",
+                "cmd.bat"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn command_line() {
+        let mut s = settings();
+        s.substr = "Parse".to_owned();
+        s.redirect_to_file = "log.txt".to_owned();
+        let cmd = DrRunCommand::new(&s, "c:\\symbols").unwrap();
+        assert_eq!(
+            cmd.to_command_line(),
+            "set \"_NT_SYMBOL_PATH=c:\\symbols\" && C:\\DynamoRIO\\bin64\\drrun.exe \
+             -c C:\\tools\\DrSymLogger.dll --printSymsExec --printSymsExecConsole \
+             --printSymsModule cmd.exe --printSymsGrep Parse -- cmd.exe /c cmd.bat > log.txt 2>&1"
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn command_line() {
+        let mut s = settings();
+        s.dr_dir = "/opt/DynamoRIO".to_owned();
+        s.dr_tool_path = "/opt/tools/libDrSymLogger.so".to_owned();
+        s.redirect_to_file = "log.txt".to_owned();
+        let cmd = DrRunCommand::new(&s, "/tmp/symbols").unwrap();
+        assert_eq!(
+            cmd.to_command_line(),
+            "_NT_SYMBOL_PATH=/tmp/symbols /opt/DynamoRIO/bin64/drrun \
+             -c /opt/tools/libDrSymLogger.so --printSymsExec --printSymsExecConsole \
+             --printSymsModule cmd.exe -- cmd.exe /c cmd.bat > log.txt 2>&1"
+        );
+    }
+
+    #[test]
+    fn quoted_target() {
+        let mut s = settings();
+        s.cmd = "\"/opt/my app/app\" -f \"a b.txt\"".to_owned();
+        let cmd = DrRunCommand::new(&s, "").unwrap();
+        assert_eq!(cmd.target_args, vec!["/opt/my app/app", "-f", "a b.txt"]);
+        assert!(cmd.to_command_line().ends_with(&format!(
+            " -- {} -f {}",
+            quote_arg("/opt/my app/app"),
+            quote_arg("a b.txt")
+        )));
+    }
+
+    #[test]
+    fn unbalanced_quotes() {
+        let mut s = settings();
+        s.cmd = "app \"unterminated".to_owned();
+        assert!(DrRunCommand::new(&s, "").is_err());
+        assert!(validate_settings(&s)
+            .iter()
+            .any(|e| e.contains("Unbalanced quotes")));
+    }
+}
@@ -0,0 +1,566 @@
+// Fetching release files, from the network or from a local mirror.
+//
+// Downloads go to `<dest>.part` first. A failed or cancelled download leaves the part file behind,
+// the next attempt asks the server for the rest of it with a Range request. The ETag or
+// Last-Modified of the first response is kept in `<dest>.part.validator` and sent as If-Range, so
+// a file changed on the server is downloaded again from the start instead of being spliced.
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+static MAX_ATTEMPTS: u32 = 4;
+// doubled after every failed attempt
+static FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+static PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+// a read stalled that long fails the attempt, it's retried like a dropped connection
+static READ_TIMEOUT: Duration = if cfg!(test) {
+    Duration::from_secs(2)
+} else {
+    Duration::from_secs(30)
+};
+
+pub enum DownloadEvent {
+    Progress {
+        done: u64,
+        total: Option<u64>, // unknown if the server doesn't say
+    },
+    Retrying {
+        attempt: u32, // the one that failed
+        delay: Duration,
+        error: String,
+    },
+    /// Archive bytes unpacked by the installer, after the download.
+    Unpacking { done: u64, total: u64 },
+    /// Something the user should know, the download goes on.
+    Warning(String),
+    /// Sent by the worker thread when everything is over, with the resulting path.
+    Done(Result<String, DownloadError>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadError {
+    Cancelled,
+    /// The server answered with an error status.
+    Status {
+        url: String,
+        status: u16,
+    },
+    /// Connection problems, including a transfer cut short.
+    Network(String),
+    /// Reading or writing local files.
+    Io(String),
+    /// The downloaded file isn't the expected one, it's removed.
+    Checksum {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+    /// The file is downloaded, but it can't be installed.
+    Install(String),
+}
+
+impl DownloadError {
+    /// Worth another attempt.
+    fn is_transient(&self) -> bool {
+        match self {
+            // 416 means the part file is stale and was removed, the next attempt starts over
+            Self::Status { status, .. } => *status >= 500 || *status == 429 || *status == 416,
+            Self::Network(_) => true,
+            Self::Cancelled | Self::Io(_) | Self::Checksum { .. } | Self::Install(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Status { url, status } => write!(f, "{} returned HTTP {}", url, status),
+            Self::Network(e) => write!(f, "network error: {}", e),
+            Self::Checksum {
+                file,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "SHA-256 of {} is {}, expected {}, the file is removed",
+                file, actual, expected
+            ),
+            Self::Io(e) | Self::Install(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// `file://` urls and plain paths are local, used for mirrors and tests.
+pub fn local_path(url: &str) -> Option<&str> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        None
+    } else {
+        Some(url.strip_prefix("file://").unwrap_or(url))
+    }
+}
+
+/// Last path component of the url, the name the file is saved under.
+pub fn file_name(url: &str) -> &str {
+    url.rsplit(['/', '\\']).next().unwrap_or(url)
+}
+
+/// Downloads `url` into `dest`, retrying transient failures with a growing delay.
+/// `notify` gets progress and retry events, `cancel` is checked between chunks.
+pub fn download(
+    url: &str,
+    dest: &Path,
+    cancel: &AtomicBool,
+    notify: &mut dyn FnMut(DownloadEvent),
+) -> Result<(), DownloadError> {
+    let part = part_path(dest);
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 1.. {
+        if cancel.load(Ordering::SeqCst) {
+            return Err(DownloadError::Cancelled);
+        }
+        let result = match local_path(url) {
+            Some(path) => copy_local(path, &part, cancel, notify),
+            None => fetch(url, &part, cancel, notify),
+        };
+        match result {
+            Ok(()) => {
+                let _ = std::fs::remove_file(validator_path(&part));
+                break;
+            }
+            Err(error) if error.is_transient() && attempt < MAX_ATTEMPTS => {
+                notify(DownloadEvent::Retrying {
+                    attempt,
+                    delay,
+                    error: error.to_string(),
+                });
+                sleep_unless_cancelled(delay, cancel);
+                delay *= 2;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    std::fs::rename(&part, dest)
+        .map_err(|e| DownloadError::Io(format!("can't move to {}: {}", dest.display(), e)))
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Where the validator of a part file is kept.
+fn validator_path(part: &Path) -> PathBuf {
+    let mut validator = part.as_os_str().to_owned();
+    validator.push(".validator");
+    PathBuf::from(validator)
+}
+
+/// Strong ETag, or Last-Modified, the values If-Range accepts.
+fn response_validator(resp: &reqwest::blocking::Response) -> Option<&str> {
+    let header = |name| resp.headers().get(name).and_then(|v| v.to_str().ok());
+    header(reqwest::header::ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(reqwest::header::LAST_MODIFIED))
+}
+
+fn sleep_unless_cancelled(delay: Duration, cancel: &AtomicBool) {
+    let until = Instant::now() + delay;
+    while Instant::now() < until && !cancel.load(Ordering::SeqCst) {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn fetch(
+    url: &str,
+    part: &Path,
+    cancel: &AtomicBool,
+    notify: &mut dyn FnMut(DownloadEvent),
+) -> Result<(), DownloadError> {
+    let have = std::fs::metadata(part).map_or(0, |m| m.len());
+    let validator = std::fs::read_to_string(validator_path(part)).ok();
+    // the blocking client's timeout is for the response headers and for every single read, not
+    // for the whole transfer, so a big file on a slow link still makes it
+    let client = reqwest::blocking::Client::builder()
+        .timeout(READ_TIMEOUT)
+        .connect_timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| DownloadError::Network(e.to_string()))?;
+    let mut request = client.get(url);
+    // without a validator there's no telling the part file is from the same file, start over
+    if let Some(validator) = validator.filter(|_| have > 0) {
+        request = request
+            .header(reqwest::header::RANGE, format!("bytes={}-", have))
+            .header(reqwest::header::IF_RANGE, validator);
+    }
+    let resp = request
+        .send()
+        .map_err(|e| DownloadError::Network(e.to_string()))?;
+    let status = resp.status();
+    let is_resumed = status == reqwest::StatusCode::PARTIAL_CONTENT;
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // the part file is bigger than the file on the server, start over
+        let _ = std::fs::remove_file(part);
+        let _ = std::fs::remove_file(validator_path(part));
+    }
+    if !status.is_success() {
+        return Err(DownloadError::Status {
+            url: url.to_owned(),
+            status: status.as_u16(),
+        });
+    }
+    // a plain 200 means the server ignored the range or the file changed, the body is the whole
+    // file and its validator replaces the old one
+    if !is_resumed {
+        let path = validator_path(part);
+        let saved = match response_validator(&resp) {
+            Some(validator) => std::fs::write(&path, validator),
+            None => std::fs::remove_file(&path).or(Ok(())),
+        };
+        saved.map_err(|e| DownloadError::Io(format!("can't write {}: {}", path.display(), e)))?;
+    }
+    let done = if is_resumed { have } else { 0 };
+    let total = resp.content_length().map(|len| len + done);
+    let out = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(is_resumed)
+        .truncate(!is_resumed)
+        .open(part)
+        .map_err(|e| DownloadError::Io(format!("can't create {}: {}", part.display(), e)))?;
+    copy_with_progress(resp, out, done, total, cancel, notify)
+}
+
+fn copy_local(
+    path: &str,
+    part: &Path,
+    cancel: &AtomicBool,
+    notify: &mut dyn FnMut(DownloadEvent),
+) -> Result<(), DownloadError> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| DownloadError::Io(format!("can't open {}: {}", path, e)))?;
+    let total = file.metadata().ok().map(|m| m.len());
+    let out = std::fs::File::create(part)
+        .map_err(|e| DownloadError::Io(format!("can't create {}: {}", part.display(), e)))?;
+    copy_with_progress(file, out, 0, total, cancel, notify)
+}
+
+fn copy_with_progress(
+    mut input: impl Read,
+    mut out: std::fs::File,
+    mut done: u64,
+    total: Option<u64>,
+    cancel: &AtomicBool,
+    notify: &mut dyn FnMut(DownloadEvent),
+) -> Result<(), DownloadError> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut last_progress = Instant::now();
+    notify(DownloadEvent::Progress { done, total });
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return Err(DownloadError::Cancelled);
+        }
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(DownloadError::Network(e.to_string())),
+        };
+        out.write_all(&buf[..n])
+            .map_err(|e| DownloadError::Io(e.to_string()))?;
+        done += n as u64;
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            notify(DownloadEvent::Progress { done, total });
+        }
+    }
+    if total.map_or(false, |total| done < total) {
+        return Err(DownloadError::Network(format!(
+            "connection closed after {} of {} bytes",
+            done,
+            total.unwrap_or_default()
+        )));
+    }
+    notify(DownloadEvent::Progress { done, total });
+    Ok(())
+}
+
+/// Normalizes a hex SHA-256 as typed by the user or written in the catalogue.
+pub fn parse_sha256(text: &str) -> Result<String, String> {
+    let hex = text.trim().to_lowercase();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "{:?} isn't a SHA-256, expected 64 hex digits",
+            text
+        ));
+    }
+    Ok(hex)
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, DownloadError> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| DownloadError::Io(format!("can't open {}: {}", path.display(), e)))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| DownloadError::Io(format!("can't read {}: {}", path.display(), e)))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Compares the file with the expected hash, a file that doesn't match is removed.
+pub fn verify_sha256(path: &Path, expected: &str) -> Result<(), DownloadError> {
+    let expected = parse_sha256(expected).map_err(DownloadError::Install)?;
+    let actual = sha256_file(path)?;
+    if actual != expected {
+        let _ = std::fs::remove_file(path);
+        return Err(DownloadError::Checksum {
+            file: path.display().to_string(),
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// "1.5 of 12.0 MB (12%)", or just the size when the total is unknown.
+pub fn progress_text(done: u64, total: Option<u64>) -> String {
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    match total {
+        Some(total) if total > 0 => format!(
+            "{:.1} of {:.1} MB ({}%)",
+            mb(done),
+            mb(total),
+            done * 100 / total
+        ),
+        _ => format!("{:.1} MB", mb(done)),
+    }
+}
+
+/// Downloads a small text file, like the release catalogue.
+pub fn fetch_text(url: &str) -> Result<String, String> {
+    if let Some(path) = local_path(url) {
+        return std::fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e));
+    }
+    let resp = reqwest::blocking::get(url).map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("{} returned {}", url, resp.status()));
+    }
+    resp.text().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+
+    static FIRST_ETAG: &str = "\"v1\"";
+    // Range and If-Range of a request
+    type Seen = (Option<String>, Option<String>);
+
+    /// Serves `body` on localhost. The first response is cut in half, the connection is dropped or
+    /// with `is_stalled` kept open without sending anything more. The following responses honor
+    /// the Range header if If-Range matches the ETag, the second response has `second_etag`.
+    /// Returns the url and the Range and If-Range headers seen.
+    fn flaky_server(
+        body: Vec<u8>,
+        second_etag: &'static str,
+        is_stalled: bool,
+    ) -> (String, std::thread::JoinHandle<Vec<Seen>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut seen = vec![];
+            let mut stalled = vec![];
+            for (i, etag) in [FIRST_ETAG, second_etag].into_iter().enumerate() {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut range = None;
+                let mut if_range = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let lower = line.to_lowercase();
+                    if let Some(value) = lower.strip_prefix("range: bytes=") {
+                        range = Some(value.trim().trim_end_matches('-').to_owned());
+                    }
+                    if lower.starts_with("if-range:") {
+                        if_range = Some(line["if-range:".len()..].trim().to_owned());
+                    }
+                }
+                let is_partial = range.is_some() && if_range.as_deref() == Some(etag);
+                let start: usize = match &range {
+                    Some(range) if is_partial => range.parse().unwrap(),
+                    _ => 0,
+                };
+                let status = if is_partial {
+                    "206 Partial Content"
+                } else {
+                    "200 OK"
+                };
+                let rest = &body[start..];
+                let header = format!(
+                    "HTTP/1.1 {}\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    etag,
+                    rest.len()
+                );
+                stream.write_all(header.as_bytes()).unwrap();
+                let sent = if i == 0 { rest.len() / 2 } else { rest.len() };
+                stream.write_all(&rest[..sent]).unwrap();
+                seen.push((range, if_range));
+                if i == 0 && is_stalled {
+                    stalled.push(stream);
+                }
+            }
+            seen
+        });
+        (url, handle)
+    }
+
+    /// Downloads from `flaky_server`, returns the retries, the last progress and the file.
+    fn download_flaky(url: &str, name: &str) -> (u32, u64, Vec<u8>) {
+        let dest =
+            std::env::temp_dir().join(format!("download_{}_{}.bin", name, std::process::id()));
+        let mut retries = 0;
+        let mut last_done = 0;
+        download(
+            url,
+            &dest,
+            &AtomicBool::new(false),
+            &mut |event| match event {
+                DownloadEvent::Retrying { .. } => retries += 1,
+                DownloadEvent::Progress { done, .. } => last_done = done,
+                DownloadEvent::Unpacking { .. }
+                | DownloadEvent::Warning(_)
+                | DownloadEvent::Done(_) => {}
+            },
+        )
+        .unwrap();
+        let downloaded = std::fs::read(&dest).unwrap();
+        std::fs::remove_file(&dest).unwrap();
+        assert!(!validator_path(&part_path(&dest)).exists());
+        (retries, last_done, downloaded)
+    }
+
+    #[test]
+    fn resumes_after_dropped_connection() {
+        let body: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let (url, server) = flaky_server(body.clone(), FIRST_ETAG, false);
+        let (retries, last_done, downloaded) = download_flaky(&url, "resume");
+        let seen = server.join().unwrap();
+        assert_eq!(retries, 1);
+        assert_eq!(
+            seen,
+            vec![
+                (None, None),
+                (Some("100000".to_owned()), Some(FIRST_ETAG.to_owned()))
+            ]
+        );
+        assert_eq!(last_done, body.len() as u64);
+        assert!(downloaded == body);
+    }
+
+    #[test]
+    fn stalled_read_is_retried() {
+        let body: Vec<u8> = (0..200_000u32).map(|i| (i * 3) as u8).collect();
+        let (url, server) = flaky_server(body.clone(), FIRST_ETAG, true);
+        let started = Instant::now();
+        let (retries, last_done, downloaded) = download_flaky(&url, "stalled");
+        let seen = server.join().unwrap();
+        assert_eq!(retries, 1);
+        assert!(started.elapsed() >= READ_TIMEOUT);
+        assert_eq!(seen[1].0.as_deref(), Some("100000"));
+        assert_eq!(last_done, body.len() as u64);
+        assert!(downloaded == body);
+    }
+
+    #[test]
+    fn changed_file_starts_over() {
+        let body: Vec<u8> = (0..200_000u32).map(|i| (i * 7) as u8).collect();
+        let (url, server) = flaky_server(body.clone(), "\"v2\"", false);
+        let (retries, last_done, downloaded) = download_flaky(&url, "changed");
+        let seen = server.join().unwrap();
+        assert_eq!(retries, 1);
+        // the range is asked for, the server sends the whole file instead
+        assert_eq!(seen[1].1.as_deref(), Some(FIRST_ETAG));
+        assert_eq!(last_done, body.len() as u64);
+        assert!(downloaded == body);
+    }
+
+    #[test]
+    fn cancel_keeps_part_file() {
+        let src = std::env::temp_dir().join(format!("download_src_{}.bin", std::process::id()));
+        std::fs::write(&src, vec![1u8; 1000]).unwrap();
+        let dest = std::env::temp_dir().join(format!("download_dst_{}.bin", std::process::id()));
+        let cancel = AtomicBool::new(false);
+        // cancelled as soon as the transfer starts
+        let result = download(&src.display().to_string(), &dest, &cancel, &mut |_| {
+            cancel.store(true, Ordering::SeqCst)
+        });
+        let is_part_left = part_path(&dest).exists();
+        let _ = std::fs::remove_file(part_path(&dest));
+        std::fs::remove_file(&src).unwrap();
+        assert_eq!(result, Err(DownloadError::Cancelled));
+        assert!(is_part_left);
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn checksum_mismatch_removes_file() {
+        let path = std::env::temp_dir().join(format!("download_sha_{}.bin", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        verify_sha256(&path, &abc.to_uppercase()).unwrap();
+        let other = "0".repeat(64);
+        let result = verify_sha256(&path, &other);
+        assert!(!path.exists());
+        assert_eq!(
+            result,
+            Err(DownloadError::Checksum {
+                file: path.display().to_string(),
+                expected: other,
+                actual: abc.to_owned(),
+            })
+        );
+        assert!(parse_sha256("abc").is_err());
+    }
+
+    #[test]
+    fn not_found_isnt_retried() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/missing.bin", listener.local_addr().unwrap());
+        // a retry would connect to the closed listener and fail with a network error instead
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .unwrap();
+        });
+        let dest = std::env::temp_dir().join(format!("download_404_{}.bin", std::process::id()));
+        let mut retries = 0;
+        let result = download(&url, &dest, &AtomicBool::new(false), &mut |event| {
+            if let DownloadEvent::Retrying { .. } = event {
+                retries += 1;
+            }
+        });
+        server.join().unwrap();
+        let _ = std::fs::remove_file(part_path(&dest));
+        assert_eq!(result, Err(DownloadError::Status { url, status: 404 }));
+        assert_eq!(retries, 0);
+    }
+}
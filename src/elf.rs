@@ -0,0 +1,358 @@
+// Function symbols of ELF modules: .symtab, .dynsym and DWARF, from the module and from its
+// separate debug file. The debug file is looked up the way gdb does it:
+//   <debug dir>/.build-id/<first 2 hex digits>/<the rest>.debug
+//   <module dir>/<debuglink>, <module dir>/.debug/<debuglink>, <debug dir>/<module dir>/<debuglink>
+// A debuglink file is only taken if its CRC matches.
+
+use crate::symbols::{ModuleSymbols, Symbol};
+use object::{Object, ObjectSection, ObjectSegment, ObjectSymbol, SymbolKind};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub static DEBUG_DIR: &str = "/usr/lib/debug";
+
+pub fn is_elf(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map_or(false, |_| &magic == b"\x7fELF")
+}
+
+/// Reads the functions of `module` and of its debug file, searched in `debug_dirs` too.
+pub fn module_symbols(module: &Path, debug_dirs: &[PathBuf]) -> Result<ModuleSymbols, String> {
+    let data = read(module)?;
+    let file = parse(module, &data)?;
+    // RVAs are relative to the first segment, executables aren't loaded at 0
+    let base = file.segments().map(|s| s.address()).min().unwrap_or(0);
+    let mut functions = BTreeMap::new();
+    let debug_file = find_debug_file(module, &file, debug_dirs);
+    if let Some(path) = &debug_file {
+        let data = read(path)?;
+        let debug = parse(path, &data)?;
+        add_debug_symbols(&mut functions, &debug, base).map_err(|e| dwarf_error(path, e))?;
+    }
+    add_debug_symbols(&mut functions, &file, base).map_err(|e| dwarf_error(module, e))?;
+    let has_debug_symbols = !functions.is_empty();
+    add_symbols(&mut functions, file.dynamic_symbols(), base);
+    Ok(ModuleSymbols {
+        module: module.to_owned(),
+        debug_file: debug_file.unwrap_or_else(|| module.to_owned()),
+        is_exports_only: !has_debug_symbols && !functions.is_empty(),
+        functions: functions.into_values().collect(),
+    })
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("can't read {}: {}", path.display(), e))
+}
+
+fn parse<'a>(path: &Path, data: &'a [u8]) -> Result<object::File<'a>, String> {
+    object::File::parse(data).map_err(|e| format!("can't parse {}: {}", path.display(), e))
+}
+
+fn dwarf_error(path: &Path, e: gimli::Error) -> String {
+    format!("can't read the DWARF of {}: {}", path.display(), e)
+}
+
+/// Separate debug file of the module, by build id first, then by debuglink.
+fn find_debug_file(module: &Path, file: &object::File, debug_dirs: &[PathBuf]) -> Option<PathBuf> {
+    if let Ok(Some(id)) = file.build_id() {
+        let hex: String = id.iter().map(|b| format!("{:02x}", b)).collect();
+        if hex.len() > 2 {
+            let found = debug_dirs
+                .iter()
+                .map(|dir| {
+                    dir.join(".build-id")
+                        .join(&hex[..2])
+                        .join(format!("{}.debug", &hex[2..]))
+                })
+                .find(|path| path.is_file());
+            if found.is_some() {
+                return found;
+            }
+        }
+    }
+    let (name, crc) = file.gnu_debuglink().ok()??;
+    let name = String::from_utf8_lossy(name).into_owned();
+    let dir = module.canonicalize().ok()?.parent()?.to_owned();
+    let mut candidates = vec![dir.join(&name), dir.join(".debug").join(&name)];
+    // the module's dir inside of the debug dir: /usr/lib/debug/usr/lib/...
+    let relative: PathBuf = dir
+        .components()
+        .filter(|c| matches!(c, std::path::Component::Normal(_)))
+        .collect();
+    candidates.extend(debug_dirs.iter().map(|d| d.join(&relative).join(&name)));
+    candidates
+        .into_iter()
+        .filter(|path| path.is_file() && !same_file(path, module))
+        .find(|path| crc32(path) == Ok(crc))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// CRC-32 of the whole file, the one in .gnu_debuglink.
+fn crc32(path: &Path) -> Result<u32, String> {
+    let data = read(path)?;
+    let mut crc = flate2::Crc::new();
+    crc.update(&data);
+    Ok(crc.sum())
+}
+
+/// .symtab has the local functions too, so DWARF is only read, slowly, if it has none.
+fn add_debug_symbols(
+    functions: &mut BTreeMap<u32, Symbol>,
+    file: &object::File,
+    base: u64,
+) -> Result<(), gimli::Error> {
+    if !add_symbols(functions, file.symbols(), base) {
+        add_dwarf(functions, file, base)?;
+    }
+    Ok(())
+}
+
+/// Returns whether there were any functions.
+fn add_symbols<'a>(
+    functions: &mut BTreeMap<u32, Symbol>,
+    symbols: impl Iterator<Item = object::Symbol<'a, 'a>>,
+    base: u64,
+) -> bool {
+    let mut is_any = false;
+    for symbol in symbols {
+        if symbol.kind() != SymbolKind::Text || !symbol.is_definition() {
+            continue;
+        }
+        let Ok(name) = symbol.name() else {
+            continue;
+        };
+        if !name.is_empty() {
+            add(functions, name, symbol.address(), symbol.size(), base);
+            is_any = true;
+        }
+    }
+    is_any
+}
+
+/// Functions already there win, the debug file comes first.
+fn add(functions: &mut BTreeMap<u32, Symbol>, name: &str, address: u64, size: u64, base: u64) {
+    let rva = address.wrapping_sub(base) as u32;
+    functions.entry(rva).or_insert_with(|| Symbol {
+        name: name.to_owned(),
+        rva,
+        size: size as u32,
+    });
+}
+
+/// Subprograms with code, by their linkage names if they have them.
+fn add_dwarf(
+    functions: &mut BTreeMap<u32, Symbol>,
+    file: &object::File,
+    base: u64,
+) -> Result<(), gimli::Error> {
+    let endian = if file.is_little_endian() {
+        gimli::RunTimeEndian::Little
+    } else {
+        gimli::RunTimeEndian::Big
+    };
+    // compressed sections are inflated, missing ones are empty
+    let load = |id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
+        Ok(file
+            .section_by_name(id.name())
+            .and_then(|section| section.uncompressed_data().ok())
+            .unwrap_or(Cow::Borrowed(&[])))
+    };
+    let sections = gimli::Dwarf::load(load)?;
+    let dwarf = sections.borrow(|section| gimli::EndianSlice::new(section, endian));
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() != gimli::DW_TAG_subprogram {
+                continue;
+            }
+            let Some(low_pc) = entry.attr_value(gimli::DW_AT_low_pc)? else {
+                continue;
+            };
+            let Some(address) = dwarf.attr_address(&unit, low_pc)? else {
+                continue;
+            };
+            let size = match entry.attr_value(gimli::DW_AT_high_pc)? {
+                Some(gimli::AttributeValue::Addr(high_pc)) => high_pc.saturating_sub(address),
+                Some(value) => value.udata_value().unwrap_or(0),
+                None => 0,
+            };
+            let name = [gimli::DW_AT_linkage_name, gimli::DW_AT_name]
+                .iter()
+                .find_map(|&at| entry.attr_value(at).ok().flatten());
+            let Some(name) = name else {
+                continue;
+            };
+            let name = dwarf.attr_string(&unit, name)?;
+            add(functions, &name.to_string_lossy(), address, size, base);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::{Elf, Function};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("elf_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn function(name: &str, rva: u32, len: u32) -> Function<'_> {
+        Function { name, rva, len }
+    }
+
+    fn names(symbols: &ModuleSymbols) -> Vec<(&str, u32, u32)> {
+        symbols
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f.rva, f.size))
+            .collect()
+    }
+
+    #[test]
+    fn symtab_or_dwarf() {
+        let dir = temp_dir("symtab");
+        let module = dir.join("libapp.so");
+        let exports = [function("_Z5parsev", 0x1100, 0x40)];
+        let dwarf = [
+            function("_Z5parsev", 0x1100, 0x40),
+            function("helper", 0x1200, 0x10),
+        ];
+        std::fs::write(
+            &module,
+            Elf {
+                symtab: &[function("_Z5parsev", 0x1100, 0x40)],
+                dynsym: &exports,
+                dwarf: &dwarf,
+                ..Elf::default()
+            }
+            .build(),
+        )
+        .unwrap();
+        let with_symtab = module_symbols(&module, &[]);
+        std::fs::write(
+            &module,
+            Elf {
+                dynsym: &exports,
+                dwarf: &dwarf,
+                ..Elf::default()
+            }
+            .build(),
+        )
+        .unwrap();
+        let dwarf_only = module_symbols(&module, &[]);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // the symbol table is complete, DWARF isn't read
+        let with_symtab = with_symtab.unwrap();
+        assert_eq!(with_symtab.debug_file, module);
+        assert!(!with_symtab.is_exports_only);
+        assert_eq!(names(&with_symtab), vec![("_Z5parsev", 0x1100, 0x40)]);
+        let dwarf_only = dwarf_only.unwrap();
+        assert!(!dwarf_only.is_exports_only);
+        assert_eq!(
+            names(&dwarf_only),
+            vec![("_Z5parsev", 0x1100, 0x40), ("helper", 0x1200, 0x10)]
+        );
+    }
+
+    #[test]
+    fn stripped_with_build_id() {
+        let dir = temp_dir("build_id");
+        let debug_dir = dir.join("debug");
+        let id = [0xab, 0xcd, 0xef, 0x01];
+        let debug = debug_dir.join(".build-id").join("ab").join("cdef01.debug");
+        std::fs::create_dir_all(debug.parent().unwrap()).unwrap();
+        std::fs::write(
+            &debug,
+            Elf {
+                dwarf: &[
+                    function("main", 0x1000, 0x20),
+                    function("static_fn", 0x1800, 0x8),
+                ],
+                build_id: Some(&id),
+                ..Elf::default()
+            }
+            .build(),
+        )
+        .unwrap();
+        let module = dir.join("app");
+        let exports = [function("exported", 0x1400, 0x10)];
+        let stripped = Elf {
+            dynsym: &exports,
+            build_id: Some(&id),
+            ..Elf::default()
+        };
+        std::fs::write(&module, stripped.build()).unwrap();
+        let with_debug = module_symbols(&module, &[debug_dir]);
+        let without = module_symbols(&module, &[dir.join("none")]);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let with_debug = with_debug.unwrap();
+        assert_eq!(with_debug.debug_file, debug);
+        assert!(!with_debug.is_exports_only);
+        assert_eq!(
+            names(&with_debug),
+            vec![
+                ("main", 0x1000, 0x20),
+                ("exported", 0x1400, 0x10),
+                ("static_fn", 0x1800, 0x8)
+            ]
+        );
+        let without = without.unwrap();
+        assert!(without.is_exports_only);
+        assert_eq!(names(&without), vec![("exported", 0x1400, 0x10)]);
+    }
+
+    #[test]
+    fn debuglink() {
+        let dir = temp_dir("debuglink");
+        let debug = dir.join(".debug").join("libapp.so.debug");
+        std::fs::create_dir_all(debug.parent().unwrap()).unwrap();
+        std::fs::write(
+            &debug,
+            Elf {
+                symtab: &[function("main", 0x1000, 0x20)],
+                ..Elf::default()
+            }
+            .build(),
+        )
+        .unwrap();
+        let crc = crc32(&debug).unwrap();
+        let debug = debug.canonicalize().unwrap();
+        let module = dir.join("libapp.so");
+        let linked = |crc| {
+            Elf {
+                debuglink: Some(("libapp.so.debug", crc)),
+                ..Elf::default()
+            }
+            .build()
+        };
+        std::fs::write(&module, linked(crc)).unwrap();
+        let found = module_symbols(&module, &[]);
+        std::fs::write(&module, linked(crc ^ 1)).unwrap();
+        let mismatch = module_symbols(&module, &[]);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let found = found.unwrap();
+        assert_eq!(found.debug_file, debug);
+        assert_eq!(names(&found), vec![("main", 0x1000, 0x20)]);
+        // no symbols at all isn't exports only
+        let mismatch = mismatch.unwrap();
+        assert!(mismatch.functions.is_empty());
+        assert!(!mismatch.is_exports_only);
+    }
+}
@@ -0,0 +1,167 @@
+// Converters from the parsed call trace to formats other tools understand.
+//
+// The trace has no timestamps, line numbers are used as time instead, so the timeline shows the
+// order and nesting of calls rather than real durations.
+
+use crate::trace::{CallEnd, CallNode, Trace};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Converts the trace file (first argument) into the output file (second argument).
+pub type ExportFn = fn(&str, &str) -> Result<(), String>;
+
+/// Trace Event Format (`chrome://tracing`, Perfetto), one B/E pair per call.
+pub fn to_chrome_trace(trace: &Trace) -> serde_json::Value {
+    enum Visit<'a> {
+        Enter(&'a CallNode),
+        Exit(&'a CallNode),
+    }
+    // calls that never ended are closed right after the last line
+    let end_of_trace = trace.line_count + 1;
+    let mut events = Vec::new();
+    let mut stack: Vec<Visit> = trace.roots.iter().rev().map(Visit::Enter).collect();
+    while let Some(visit) = stack.pop() {
+        match visit {
+            Visit::Enter(node) => {
+                events.push(json!({
+                    "name": node.name,
+                    "ph": "B",
+                    "ts": node.line,
+                    "pid": 1,
+                    "tid": 1,
+                    "args": { "line": node.line, "depth": node.depth },
+                }));
+                stack.push(Visit::Exit(node));
+                stack.extend(node.children.iter().rev().map(Visit::Enter));
+            }
+            Visit::Exit(node) => {
+                let args = match node.end {
+                    CallEnd::Returned(Some(ret)) => json!({ "ret": format!("0x{:x}", ret) }),
+                    CallEnd::Returned(None) => json!({}),
+                    CallEnd::Unwound => json!({ "end": "unwound" }),
+                    CallEnd::Truncated => json!({ "end": "truncated" }),
+                };
+                events.push(json!({
+                    "name": node.name,
+                    "ph": "E",
+                    "ts": node.exit_line.unwrap_or(end_of_trace),
+                    "pid": 1,
+                    "tid": 1,
+                    "args": args,
+                }));
+            }
+        }
+    }
+    json!({ "traceEvents": events, "displayTimeUnit": "ns" })
+}
+
+pub fn export_chrome_trace(trace_path: &str, out_path: &str) -> Result<(), String> {
+    let trace =
+        Trace::load(trace_path).map_err(|e| format!("can't read {:?}: {}", trace_path, e))?;
+    let json = serde_json::to_string(&to_chrome_trace(&trace)).map_err(|e| e.to_string())?;
+    write_output(out_path, &json)
+}
+
+/// Brendan Gregg's folded stacks (`a;b;c count`) for `inferno`/`flamegraph.pl`. Every call counts
+/// as one sample of its own stack, so a frame's width is the number of calls under it.
+pub fn to_folded(trace: &Trace) -> String {
+    let mut stacks: BTreeMap<String, usize> = BTreeMap::new();
+    let mut stack: Vec<(String, &CallNode)> = trace
+        .roots
+        .iter()
+        .map(|node| (String::new(), node))
+        .collect();
+    while let Some((prefix, node)) = stack.pop() {
+        // ';' separates frames, it can't appear in a frame name
+        let name = node.name.replace(';', ":");
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{};{}", prefix, name)
+        };
+        stack.extend(node.children.iter().map(|child| (path.clone(), child)));
+        *stacks.entry(path).or_insert(0) += 1;
+    }
+    let mut out = String::new();
+    for (path, count) in stacks {
+        out.push_str(&format!("{} {}\n", path, count));
+    }
+    out
+}
+
+pub fn export_folded(trace_path: &str, out_path: &str) -> Result<(), String> {
+    let trace =
+        Trace::load(trace_path).map_err(|e| format!("can't read {:?}: {}", trace_path, e))?;
+    write_output(out_path, &to_folded(&trace))
+}
+
+/// Writes to the file, or to stdout when the path is `-`.
+fn write_output(out_path: &str, contents: &str) -> Result<(), String> {
+    if out_path == "-" {
+        print!("{}", contents);
+        return Ok(());
+    }
+    std::fs::write(out_path, contents).map_err(|e| format!("can't write {:?}: {}", out_path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chrome_nesting_and_args() {
+        let trace = Trace::parse(
+            " -> BatLoop
+     -> OpenPosBat
+     <- OpenPosBat (0x0000000000000003)
+     -> Parser
+",
+        );
+        let json = to_chrome_trace(&trace);
+        let events = json["traceEvents"].as_array().unwrap();
+        let phases: Vec<(&str, &str, u64)> = events
+            .iter()
+            .map(|e| {
+                (
+                    e["ph"].as_str().unwrap(),
+                    e["name"].as_str().unwrap(),
+                    e["ts"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            phases,
+            vec![
+                ("B", "BatLoop", 1),
+                ("B", "OpenPosBat", 2),
+                ("E", "OpenPosBat", 3),
+                ("B", "Parser", 4),
+                ("E", "Parser", 5),
+                ("E", "BatLoop", 5),
+            ]
+        );
+        assert_eq!(events[2]["args"]["ret"], "0x3");
+        assert_eq!(events[4]["args"]["end"], "truncated");
+    }
+
+    #[test]
+    fn folded_stacks() {
+        let trace = Trace::parse(
+            " -> ParseS0
+     -> BinaryOperator
+         -> ParseS1
+         <- ParseS1 (0x0000000000000000)
+         -> ParseS1
+         <- ParseS1 (0x0000000000000000)
+     <- BinaryOperator (0x0000000000000000)
+ <- ParseS0 (0x0000000000000000)
+ -> Lex
+ <- Lex (0x0000000000004000)
+",
+        );
+        assert_eq!(
+            to_folded(&trace),
+            "Lex 1\nParseS0 1\nParseS0;BinaryOperator 1\nParseS0;BinaryOperator;ParseS1 2\n"
+        );
+    }
+}
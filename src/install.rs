@@ -0,0 +1,414 @@
+// DynamoRIO release catalogue and side-by-side installs into the managed tools dir.
+//
+// Tools dir layout:
+//   dynamorio/<version>/   unpacked releases
+//   downloads/             archives being installed and the DrSymLogger tool before it's moved
+//                          to its destination, only resumable `.part` files are left there
+//   installed.json         installed versions and where their DynamoRIO dir is
+
+use crate::archive::{extract_to_dir, MAX_UNPACKED_SIZE};
+use crate::command::{check_dr_dir, DRRUN_NAME};
+use crate::download::{self, DownloadError, DownloadEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+static BUILTIN_CATALOGUE: &str = include_str!("../dynamorio_releases.json");
+static INSTALLED_FILE: &str = "installed.json";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Catalogue {
+    pub releases: Vec<Release>, // newest first
+    #[serde(default)]
+    pub drsymlogger: Option<Release>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Release {
+    pub version: String,
+    pub assets: BTreeMap<String, Asset>, // by `std::env::consts::OS`
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Asset {
+    pub url: String,
+    #[serde(default)]
+    pub sha256: Option<String>, // hex
+}
+
+impl Catalogue {
+    /// `source` is an url or a local path, the built-in list is used when it's empty.
+    pub fn load(source: &str) -> Result<Self, String> {
+        let text = if source.is_empty() {
+            BUILTIN_CATALOGUE.to_owned()
+        } else {
+            download::fetch_text(source)?
+        };
+        serde_json::from_str(&text).map_err(|e| format!("invalid catalogue {:?}: {}", source, e))
+    }
+
+    pub fn find(&self, version: &str) -> Option<&Release> {
+        self.releases.iter().find(|r| r.version == version)
+    }
+
+    /// The newest release with a build for this OS.
+    pub fn latest(&self) -> Option<&Release> {
+        self.releases.iter().find(|r| r.host_asset().is_some())
+    }
+}
+
+impl Release {
+    pub fn host_asset(&self) -> Option<&Asset> {
+        self.assets.get(std::env::consts::OS)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Installed {
+    pub dynamorio: BTreeMap<String, InstalledRelease>, // by version
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstalledRelease {
+    pub path: String, // DynamoRIO dir, the one with bin64 in it
+    pub url: String,
+}
+
+impl Installed {
+    pub fn load(tools_dir: &Path) -> Result<Self, String> {
+        let path = tools_dir.join(INSTALLED_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("invalid {}: {}", path.display(), e))
+    }
+
+    fn save(&self, tools_dir: &Path) -> Result<(), String> {
+        let path = tools_dir.join(INSTALLED_FILE);
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(&path, text).map_err(|e| format!("can't write {}: {}", path.display(), e))
+    }
+}
+
+/// The tools dir setting, or the per-user data dir when it's empty.
+pub fn tools_dir(setting: &str) -> PathBuf {
+    if !setting.is_empty() {
+        return PathBuf::from(setting);
+    }
+    match dirs::data_local_dir() {
+        Some(dir) => dir.join("dr_symlogger_launcher").join("tools"),
+        None => PathBuf::from("tools"),
+    }
+}
+
+/// DynamoRIO dir of an installed version.
+pub fn installed_dr_dir(tools_dir: &Path, version: &str) -> Result<PathBuf, String> {
+    let installed = Installed::load(tools_dir)?;
+    let release = installed.dynamorio.get(version).ok_or_else(|| {
+        format!(
+            "DynamoRIO {} isn't installed in {}",
+            version,
+            tools_dir.display()
+        )
+    })?;
+    Ok(PathBuf::from(&release.path))
+}
+
+/// Checks a downloaded asset against `sha256`, or the catalogue's checksum if it's `None`.
+/// Without either the file is kept and a warning is sent.
+pub fn verify_asset(
+    path: &Path,
+    asset: &Asset,
+    sha256: Option<&str>,
+    notify: &mut dyn FnMut(DownloadEvent),
+) -> Result<(), DownloadError> {
+    match sha256.or(asset.sha256.as_deref()) {
+        Some(expected) => download::verify_sha256(path, expected),
+        None => {
+            notify(DownloadEvent::Warning(format!(
+                "no SHA-256 for {}, {} isn't verified",
+                asset.url,
+                path.display()
+            )));
+            Ok(())
+        }
+    }
+}
+
+/// Downloads the release for this OS and unpacks it into `<tools_dir>/dynamorio/<version>`,
+/// replacing a previous install of the same version. Returns the DynamoRIO dir.
+/// The archive is verified before it's unpacked, see `verify_asset`.
+/// `cancel` and `notify` are passed to `download::download`.
+pub fn install_dr(
+    release: &Release,
+    tools_dir: &Path,
+    sha256: Option<&str>,
+    cancel: &AtomicBool,
+    notify: &mut dyn FnMut(DownloadEvent),
+) -> Result<PathBuf, DownloadError> {
+    let asset = release.host_asset().ok_or_else(|| {
+        DownloadError::Install(format!(
+            "DynamoRIO {} has no {} build",
+            release.version,
+            std::env::consts::OS
+        ))
+    })?;
+    let archive = downloads_dir(tools_dir)?.join(download::file_name(&asset.url));
+    download::download(&asset.url, &archive, cancel, notify)?;
+    verify_asset(&archive, asset, sha256, notify)?;
+    let dest = tools_dir.join("dynamorio").join(&release.version);
+    let unpacked = unpack(&archive, &dest, notify);
+    let _ = std::fs::remove_file(&archive);
+    let dr_dir = unpacked.map_err(DownloadError::Install)?;
+
+    let mut installed = Installed::load(tools_dir).map_err(DownloadError::Install)?;
+    installed.dynamorio.insert(
+        release.version.clone(),
+        InstalledRelease {
+            path: dr_dir.display().to_string(),
+            url: asset.url.clone(),
+        },
+    );
+    installed.save(tools_dir).map_err(DownloadError::Io)?;
+    Ok(dr_dir)
+}
+
+/// Downloads the catalogue's DrSymLogger build for this OS into the downloads dir, verifies it
+/// against `sha256` or the catalogue's checksum and moves it into `dest_dir`. Returns the path of
+/// the tool.
+pub fn install_tool(
+    catalogue: &Catalogue,
+    tools_dir: &Path,
+    dest_dir: &Path,
+    sha256: Option<&str>,
+    cancel: &AtomicBool,
+    notify: &mut dyn FnMut(DownloadEvent),
+) -> Result<PathBuf, DownloadError> {
+    let asset = catalogue
+        .drsymlogger
+        .as_ref()
+        .and_then(|release| release.host_asset())
+        .ok_or_else(|| {
+            DownloadError::Install(format!(
+                "no DrSymLogger {} build in the catalogue",
+                std::env::consts::OS
+            ))
+        })?;
+    let name = download::file_name(&asset.url);
+    let staged = downloads_dir(tools_dir)?.join(name);
+    download::download(&asset.url, &staged, cancel, notify)?;
+    verify_asset(&staged, asset, sha256, notify)?;
+    std::fs::create_dir_all(dest_dir)
+        .map_err(|e| DownloadError::Io(format!("can't create {}: {}", dest_dir.display(), e)))?;
+    let dest = dest_dir.join(name);
+    let moved = move_file(&staged, &dest);
+    let _ = std::fs::remove_file(&staged);
+    moved.map_err(DownloadError::Io)?;
+    Ok(dest)
+}
+
+/// Creates the downloads dir, removing finished downloads left by an interrupted install.
+fn downloads_dir(tools_dir: &Path) -> Result<PathBuf, DownloadError> {
+    let downloads = tools_dir.join("downloads");
+    std::fs::create_dir_all(&downloads)
+        .map_err(|e| DownloadError::Io(format!("can't create {}: {}", downloads.display(), e)))?;
+    let entries = std::fs::read_dir(&downloads)
+        .map_err(|e| DownloadError::Io(format!("can't read {}: {}", downloads.display(), e)))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_file() && path.extension().map_or(true, |ext| ext != "part") {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+    Ok(downloads)
+}
+
+/// Renames, or copies when `to` is on another file system.
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)
+        .map_err(|e| format!("can't copy {} to {}: {}", from.display(), to.display(), e))?;
+    std::fs::remove_file(from).map_err(|e| format!("can't remove {}: {}", from.display(), e))
+}
+
+fn unpack(
+    archive: &Path,
+    dest: &Path,
+    notify: &mut dyn FnMut(DownloadEvent),
+) -> Result<PathBuf, String> {
+    // replaces a previous install only once everything is unpacked
+    extract_to_dir(archive, dest, MAX_UNPACKED_SIZE, &mut |done, total| {
+        notify(DownloadEvent::Unpacking { done, total })
+    })?;
+    find_dr_root(dest)
+}
+
+/// Releases unpack into a single top level dir, the DynamoRIO dir is either `dir` or that one.
+fn find_dr_root(dir: &Path) -> Result<PathBuf, String> {
+    if check_dr_dir(&dir.display().to_string()) {
+        return Ok(dir.to_owned());
+    }
+    let entries = std::fs::read_dir(dir).map_err(|e| e.to_string())?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if check_dr_dir(&path.display().to_string()) {
+            return Ok(path);
+        }
+    }
+    Err(format!(
+        "there is no bin64/{} in {}",
+        DRRUN_NAME,
+        dir.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::DR_TOOL_NAME;
+    use std::io::Write;
+
+    #[test]
+    fn builtin_catalogue() {
+        let catalogue = Catalogue::load("").unwrap();
+        assert!(!catalogue.releases.is_empty());
+        for release in &catalogue.releases {
+            assert!(release.assets["windows"].url.ends_with(".zip"));
+            assert!(release.assets["linux"].url.ends_with(".tar.gz"));
+        }
+        assert_eq!(
+            catalogue.latest().unwrap().version,
+            catalogue.releases[0].version
+        );
+        assert!(catalogue.find("9.0.1").is_some());
+        let drsymlogger = catalogue.drsymlogger.as_ref().unwrap();
+        assert!(drsymlogger.assets["windows"].url.ends_with(".dll"));
+        // published checksums are well-formed, the assets without one install with a warning
+        for release in catalogue.releases.iter().chain([drsymlogger]) {
+            for asset in release.assets.values() {
+                if let Some(sha256) = &asset.sha256 {
+                    assert!(download::parse_sha256(sha256).is_ok(), "{}", asset.url);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn install_from_local_mirror() {
+        let mirror = std::env::temp_dir().join(format!("dr_mirror_{}", std::process::id()));
+        let tools = mirror.join("tools");
+        std::fs::create_dir_all(&mirror).unwrap();
+        let zip_path = mirror.join("DynamoRIO-Test-1.0.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+        let options = zip::write::FileOptions::default();
+        zip.add_directory("DynamoRIO-Test-1.0/bin64/", options)
+            .unwrap();
+        zip.start_file(format!("DynamoRIO-Test-1.0/bin64/{}", DRRUN_NAME), options)
+            .unwrap();
+        zip.write_all(b"drrun").unwrap();
+        zip.finish().unwrap();
+        let catalogue_path = mirror.join("catalogue.json");
+        let catalogue = serde_json::json!({
+            "releases": [{
+                "version": "1.0",
+                "assets": { std::env::consts::OS: { "url": zip_path.display().to_string() } }
+            }]
+        });
+        std::fs::write(&catalogue_path, catalogue.to_string()).unwrap();
+
+        let catalogue = Catalogue::load(&catalogue_path.display().to_string()).unwrap();
+        let release = catalogue.latest().unwrap();
+        let cancel = AtomicBool::new(false);
+        let mut warnings = 0;
+        let dr_dir = install_dr(release, &tools, None, &cancel, &mut |event| {
+            if let DownloadEvent::Warning(_) = event {
+                warnings += 1;
+            }
+        })
+        .unwrap();
+        // installing again replaces the previous install
+        let sha256 = download::sha256_file(&zip_path).unwrap();
+        let dr_dir2 = install_dr(release, &tools, Some(&sha256), &cancel, &mut |_| {}).unwrap();
+        let bad = install_dr(release, &tools, Some(&"0".repeat(64)), &cancel, &mut |_| {});
+        let recorded = installed_dr_dir(&tools, "1.0");
+        let is_archive_left = tools
+            .join("downloads")
+            .join("DynamoRIO-Test-1.0.zip")
+            .exists();
+        std::fs::remove_dir_all(&mirror).unwrap();
+
+        assert_eq!(
+            dr_dir,
+            tools
+                .join("dynamorio")
+                .join("1.0")
+                .join("DynamoRIO-Test-1.0")
+        );
+        assert_eq!(dr_dir2, dr_dir);
+        assert_eq!(warnings, 1);
+        assert!(matches!(bad, Err(DownloadError::Checksum { .. })));
+        assert_eq!(recorded.unwrap(), dr_dir);
+        assert!(!is_archive_left);
+        assert!(installed_dr_dir(&tools, "2.0").is_err());
+    }
+
+    #[test]
+    fn tool_lands_in_dest_dir() {
+        let mirror = std::env::temp_dir().join(format!("tool_mirror_{}", std::process::id()));
+        let tools = mirror.join("tools");
+        let dest = mirror.join("dest");
+        std::fs::create_dir_all(&dest).unwrap();
+        let tool_path = mirror.join(DR_TOOL_NAME);
+        std::fs::write(&tool_path, b"tool").unwrap();
+        let sha256 = download::sha256_file(&tool_path).unwrap();
+        let catalogue = serde_json::json!({
+            "releases": [],
+            "drsymlogger": {
+                "version": "1.0",
+                "assets": { std::env::consts::OS: {
+                    "url": tool_path.display().to_string(),
+                    "sha256": sha256,
+                } }
+            }
+        });
+        let catalogue: Catalogue = serde_json::from_value(catalogue).unwrap();
+        // an archive left behind by an interrupted install
+        std::fs::create_dir_all(tools.join("downloads")).unwrap();
+        std::fs::write(tools.join("downloads").join("old.zip"), b"old").unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut warnings = 0;
+        let path = install_tool(&catalogue, &tools, &dest, None, &cancel, &mut |event| {
+            if let DownloadEvent::Warning(_) = event {
+                warnings += 1;
+            }
+        });
+        let tool_text = std::fs::read(dest.join(DR_TOOL_NAME));
+        // the given checksum wins over the catalogue's one
+        let other = mirror.join("other");
+        let bad = install_tool(
+            &catalogue,
+            &tools,
+            &other,
+            Some(&"0".repeat(64)),
+            &cancel,
+            &mut |_| {},
+        );
+        let is_other_written = other.join(DR_TOOL_NAME).exists();
+        let downloads_left = std::fs::read_dir(tools.join("downloads")).unwrap().count();
+        std::fs::remove_dir_all(&mirror).unwrap();
+
+        assert_eq!(path.unwrap(), dest.join(DR_TOOL_NAME));
+        assert_eq!(tool_text.unwrap(), b"tool");
+        assert_eq!(warnings, 0);
+        assert_eq!(downloads_left, 0);
+        assert!(matches!(bad, Err(DownloadError::Checksum { .. })));
+        assert!(!is_other_written);
+    }
+}
@@ -0,0 +1,143 @@
+// Window listing catalogued DynamoRIO releases and the installed ones.
+
+use crate::download;
+use crate::install::{tools_dir, Catalogue, Installed, Release};
+use crate::settings::Settings;
+use crate::DownloadState;
+use eframe::egui;
+
+pub enum InstallAction {
+    Reload,
+    Install(Release, Option<String>), // user-supplied SHA-256
+    Use(String),                      // DynamoRIO dir
+}
+
+#[derive(Default)]
+pub struct InstallView {
+    pub open: bool,
+    pub catalogue: Option<Catalogue>,
+    pub installed: Installed,
+    pub sha256: String, // overrides the catalogue's checksum for the next install
+}
+
+impl InstallView {
+    /// Catalogue and tools dir are edited in place, everything else is returned as an action.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        settings: &mut Settings,
+        download: Option<&DownloadState>,
+    ) -> Option<InstallAction> {
+        let is_busy = download.is_some();
+        let mut action = None;
+        let mut open = self.open;
+        egui::Window::new("Install DynamoRIO")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                egui::Grid::new("install_grid")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Catalogue");
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut settings.catalogue_url)
+                                    .hint_text("built-in"),
+                            )
+                            .on_hover_text("Url or path of a releases list, e.g. a local mirror");
+                            if ui
+                                .button("🔄")
+                                .on_hover_text("Reload the catalogue and the installed versions")
+                                .clicked()
+                            {
+                                action = Some(InstallAction::Reload);
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Tools dir");
+                        ui.add_enabled(
+                            !is_busy,
+                            egui::TextEdit::singleline(&mut settings.tools_dir)
+                                .hint_text(tools_dir("").display().to_string()),
+                        );
+                        ui.end_row();
+
+                        ui.label("SHA-256");
+                        ui.add_enabled(
+                            !is_busy,
+                            egui::TextEdit::singleline(&mut self.sha256)
+                                .hint_text("from the catalogue"),
+                        )
+                        .on_hover_text("Expected checksum of the archive to install next");
+                        ui.end_row();
+                    });
+                ui.separator();
+
+                let Some(catalogue) = &self.catalogue else {
+                    ui.label("No catalogue loaded");
+                    return;
+                };
+                egui::Grid::new("releases_grid")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for release in &catalogue.releases {
+                            ui.label(&release.version);
+                            if let Some(installed) = self.installed.dynamorio.get(&release.version)
+                            {
+                                ui.label(&installed.path);
+                                if ui
+                                    .add_enabled(!is_busy, egui::Button::new("Use"))
+                                    .on_hover_text("Set as the DynamoRIO dir")
+                                    .clicked()
+                                {
+                                    action = Some(InstallAction::Use(installed.path.clone()));
+                                }
+                            } else if let Some(asset) = release.host_asset() {
+                                let label = ui.label(download::file_name(&asset.url));
+                                if let Some(sha256) = &asset.sha256 {
+                                    label.on_hover_text(format!("SHA-256 {}", sha256));
+                                }
+                                if ui
+                                    .add_enabled(!is_busy, egui::Button::new("Install"))
+                                    .on_hover_text(&asset.url)
+                                    .clicked()
+                                {
+                                    let sha256 = self.sha256.trim();
+                                    let sha256 = (!sha256.is_empty()).then(|| sha256.to_owned());
+                                    action = Some(InstallAction::Install(release.clone(), sha256));
+                                }
+                            } else {
+                                ui.label(format!("no {} build", std::env::consts::OS));
+                                ui.label("");
+                            }
+                            ui.end_row();
+                        }
+                        // installed from another catalogue
+                        for (version, installed) in &self.installed.dynamorio {
+                            if catalogue.find(version).is_some() {
+                                continue;
+                            }
+                            ui.label(version);
+                            ui.label(&installed.path);
+                            if ui
+                                .add_enabled(!is_busy, egui::Button::new("Use"))
+                                .on_hover_text("Set as the DynamoRIO dir")
+                                .clicked()
+                            {
+                                action = Some(InstallAction::Use(installed.path.clone()));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                if let Some(download) = download {
+                    ui.horizontal(|ui| {
+                        ui.label("Installing");
+                        download.show(ui);
+                    });
+                }
+            });
+        self.open = open;
+        action
+    }
+}
@@ -2,76 +2,54 @@
 
 // import for MessageBox
 
+mod archive;
+mod cli;
+mod cmdline;
+mod command;
+mod download;
+mod elf;
+mod export;
+mod install;
+mod install_view;
+mod runner;
+mod settings;
+mod symbol_path;
+mod symbol_path_view;
+mod symbol_view;
+mod symbols;
+mod symstore;
+mod symstore_view;
+#[cfg(test)]
+mod test_files;
+mod trace;
+mod trace_view;
+
+use cmdline::split_command_line;
+use command::{check_dr_dir, check_dr_tool_path, DrRunCommand, DR_TOOL_NAME};
+use download::{progress_text, DownloadError, DownloadEvent};
 use eframe::egui;
-// reqwest
-use reqwest;
-use serde::{Deserialize, Serialize};
-use shlex::Shlex;
+use install::{install_dr, install_tool, tools_dir, Catalogue, Installed, Release};
+use install_view::{InstallAction, InstallView};
+use runner::{RunEvent, RunHandle, StopReason};
+use settings::{config_path, DrToolInstrumentationMode, Profiles, Settings};
+use std::collections::VecDeque;
 use std::io::Write;
 use std::path::Path;
-use std::sync::mpsc;
-use zip;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use symbol_path::{check_symbol_path, split_stores, SYMBOL_PATH_ENV};
+use symbol_path_view::SymbolPathView;
+use symbol_view::SymbolView;
+use symbols::{matching, module_symbols, ModuleSymbols};
+use symstore_view::SymstoreView;
+use trace_view::TraceView;
 
 // Alternative emoji list:
 // ❗
 
 // static variables
-static DR_DOWNLOAD_URL: &str = "https://github.com/DynamoRIO/dynamorio/releases/download/release_9.0.1/DynamoRIO-Windows-9.0.1.zip";
-static DR_TOOL_DOWNLOAD_URL: &str =
-    "https://github.com/expend20/DrSymLogger/releases/download/v0.0.1/DrSymLogger.dll";
-
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
-enum DrToolInstrumentationMode {
-    Exec,
-    Inst,
-    Invalid,
-}
-
-impl Default for DrToolInstrumentationMode {
-    fn default() -> Self {
-        DrToolInstrumentationMode::Invalid
-    }
-}
-
-#[derive(Serialize, Deserialize, Clone, Default)]
-struct Settings {
-    dr_dir: String,
-    dr_tool_path: String,
-    inst_module: String,
-    inst_mode: DrToolInstrumentationMode,
-    substr: String,
-    redirect_to_file: String, // 2>&1 > file.txt
-    cmd: String,
-}
-
-impl Settings {
-    fn default() -> Self {
-        Self {
-            dr_dir: "".to_owned(),
-            dr_tool_path: "".to_owned(),
-            inst_module: "cmd.exe".to_owned(),
-            inst_mode: DrToolInstrumentationMode::Exec,
-            substr: "".to_owned(),
-            redirect_to_file: "log.txt".to_owned(),
-            cmd: "cmd.exe /c cmd.bat".to_owned(),
-        }
-    }
-    fn new() -> Self {
-        // try to read settings.json
-        let settings_path = Path::new("settings.json");
-        if settings_path.exists() {
-            // read settings
-            let settings_str = std::fs::read_to_string(settings_path).unwrap();
-            let settings: Settings = serde_json::from_str(&settings_str).unwrap();
-            return settings;
-        }
-        Self::default()
-    }
-    fn save(&self) {
-        let settings_str = serde_json::to_string(self).unwrap();
-        std::fs::write("settings.json", settings_str).unwrap();
-    }
-}
+// the full output is in the redirect file, the output panel only keeps the tail
+static MAX_OUTPUT_LINES: usize = 10_000;
 
 fn log(msg: &str) {
     // open file "log.txt" in append mode
@@ -86,7 +64,14 @@ fn log(msg: &str) {
 
 fn main() -> Result<(), eframe::Error> {
     log("main started\n");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("--headless") {
+        #[cfg(windows)]
+        attach_parent_console();
+        std::process::exit(cli::run(&args[1..]));
+    }
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    let config = config_path(cli::option_value(&args, "--config").ok().flatten());
     let options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(800.0, 600.0)),
         // disable resizing
@@ -97,41 +82,184 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "My egui App",
         options,
-        Box::new(|_cc| Box::new(MyApp::new())),
+        Box::new(move |_cc| Box::new(MyApp::new(&config))),
     )
 }
 
+#[cfg(windows)]
+fn attach_parent_console() {
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // release builds use the windows subsystem, so we have to reattach to the caller's console
+    // to make stdout/stderr visible there
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// A download running on a worker thread, updated from its events.
+struct DownloadState {
+    cancel: Arc<AtomicBool>,
+    done: u64,
+    total: Option<u64>,
+    is_unpacking: bool, // the download is over, it can't be cancelled anymore
+}
+
+impl DownloadState {
+    fn new() -> Self {
+        Self {
+            cancel: Arc::new(AtomicBool::new(false)),
+            done: 0,
+            total: None,
+            is_unpacking: false,
+        }
+    }
+
+    fn show(&self, ui: &mut egui::Ui) {
+        let fraction = match self.total {
+            Some(total) if total > 0 => self.done as f32 / total as f32,
+            _ => 0.0,
+        };
+        ui.add(
+            egui::ProgressBar::new(fraction)
+                .desired_width(200.0)
+                .text(if self.is_unpacking {
+                    format!("Unpacking {}", progress_text(self.done, self.total))
+                } else {
+                    progress_text(self.done, self.total)
+                }),
+        );
+        let is_cancelled = self.cancel.load(Ordering::SeqCst);
+        if ui
+            .add_enabled(!is_cancelled && !self.is_unpacking, egui::Button::new("✖"))
+            .on_hover_text("Cancel the download, it's resumed next time")
+            .clicked()
+        {
+            self.cancel.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Error icon with the reason and a retry button, returns true if retry is clicked.
+fn show_download_error(ui: &mut egui::Ui, error: &DownloadError) -> bool {
+    ui.colored_label(egui::Color32::RED, "☹")
+        .on_hover_text(format!("Download failed: {}", error));
+    ui.button("🔁")
+        .on_hover_text("Retry the download")
+        .clicked()
+}
+
+/// Applies the worker's events, returns the result once it's done.
+fn poll_download(
+    rc: &mpsc::Receiver<DownloadEvent>,
+    state: &mut Option<DownloadState>,
+    log_text: &mut String,
+) -> Option<Result<String, DownloadError>> {
+    while let Ok(event) = rc.try_recv() {
+        match event {
+            DownloadEvent::Progress { done, total } => {
+                if let Some(state) = state {
+                    state.done = done;
+                    state.total = total;
+                }
+            }
+            DownloadEvent::Unpacking { done, total } => {
+                if let Some(state) = state {
+                    state.done = done;
+                    state.total = Some(total);
+                    state.is_unpacking = true;
+                }
+            }
+            DownloadEvent::Retrying {
+                attempt,
+                delay,
+                error,
+            } => log_text.push_str(
+                format!(
+                    "Download attempt {} failed: {}, retrying in {} s\n",
+                    attempt,
+                    error,
+                    delay.as_secs()
+                )
+                .as_str(),
+            ),
+            DownloadEvent::Warning(warning) => {
+                log_text.push_str(format!("Warning: {}\n", warning).as_str())
+            }
+            DownloadEvent::Done(result) => {
+                *state = None;
+                return Some(result);
+            }
+        }
+    }
+    None
+}
+
 struct MyApp {
-    settings: Settings,
+    profiles: Profiles,
+    profile_name: String, // for new, duplicated and renamed profiles
+    settings: Settings,   // working copy of the current profile
     settings_cached: Settings,
+    is_settings_reloaded: bool,
     is_dr_dir_ok: bool,
     is_dr_tool_path_ok: bool,
-    is_dr_download_started: bool,
-    is_dr_tool_download_started: bool,
-    is_quote_in_cmd: bool,
+    dr_download: Option<DownloadState>,
+    tool_download: Option<DownloadState>,
+    // the last failed download and what's needed to retry it
+    dr_download_error: Option<DownloadError>,
+    dr_download_release: Option<(Release, Option<String>)>, // and the user-supplied SHA-256
+    tool_download_error: Option<DownloadError>,
+    tool_download_dir: String,
+    tool_download_sha256: Option<String>, // the user-supplied SHA-256
+    tool_sha256: String, // overrides the catalogue's checksum for the next download
+    is_cmd_unbalanced: bool,
     log_text: String,
-    on_done_dr_down_tx: mpsc::SyncSender<Option<String>>,
-    on_done_tool_down_tx: mpsc::SyncSender<Option<String>>,
-    on_done_dr_down_rc: mpsc::Receiver<Option<String>>,
-    on_done_tool_down_rc: mpsc::Receiver<Option<String>>,
-    symbol_path: String,
+    on_done_dr_down_tx: mpsc::Sender<DownloadEvent>,
+    on_done_tool_down_tx: mpsc::Sender<DownloadEvent>,
+    on_done_dr_down_rc: mpsc::Receiver<DownloadEvent>,
+    on_done_tool_down_rc: mpsc::Receiver<DownloadEvent>,
+    symbol_path: String, // local stores passed to DR
     cmd: String,
+    run_cmd: Option<DrRunCommand>,
+    run: Option<RunHandle>,
+    run_output: VecDeque<(bool, String)>, // (is stderr, line)
+    trace_view: TraceView,
+    install_view: InstallView,
+    catalogue_rc: Option<mpsc::Receiver<Result<Catalogue, String>>>,
+    symbol_path_view: SymbolPathView,
+    symstore_view: SymstoreView,
+    symbols_rc: Option<mpsc::Receiver<Result<ModuleSymbols, String>>>,
+    symbol_view: SymbolView,
 }
 
 impl MyApp {
-    fn new() -> Self {
+    fn new(config: &Path) -> Self {
         // create channel for communication between threads
-        let (on_done_tx, on_done_rc) = mpsc::sync_channel::<Option<String>>(0);
-        let (on_tool_done_tx, on_tool_done_rc) = mpsc::sync_channel::<Option<String>>(0);
-        let settings = Settings::new();
+        // progress events come before the final Done, the worker shouldn't wait for the UI
+        let (on_done_tx, on_done_rc) = mpsc::channel::<DownloadEvent>();
+        let (on_tool_done_tx, on_tool_done_rc) = mpsc::channel::<DownloadEvent>();
+        let mut log_text = String::new();
+        let profiles = Profiles::load(config, Path::new("."), &mut log_text);
+        let settings = profiles.current().clone();
         let settings_cached = Settings::default();
-        let mut s = Self {
+        Self {
+            profiles,
+            profile_name: "".to_owned(),
+            is_settings_reloaded: true,
             is_dr_dir_ok: false,
             is_dr_tool_path_ok: false,
-            is_dr_download_started: false,
-            is_dr_tool_download_started: false,
-            is_quote_in_cmd: false,
-            log_text: "".to_owned(),
+            dr_download: None,
+            tool_download: None,
+            dr_download_error: None,
+            dr_download_release: None,
+            tool_download_error: None,
+            tool_download_dir: "".to_owned(),
+            tool_download_sha256: None,
+            tool_sha256: "".to_owned(),
+            is_cmd_unbalanced: false,
+            log_text,
             on_done_dr_down_tx: on_done_tx,
             on_done_tool_down_tx: on_tool_done_tx,
             on_done_dr_down_rc: on_done_rc,
@@ -140,53 +268,24 @@ impl MyApp {
             settings,
             settings_cached,
             cmd: "".to_owned(),
-        };
-        s.check_symbol_path();
-        s
-    }
-
-    fn check_symbol_path(&mut self) {
-        // get env variable "_NT_SYMBOL_PATH"
-        let symbol_path = std::env::var("_NT_SYMBOL_PATH").unwrap();
-        if symbol_path.is_empty() {
-            self.log_text.push_str(
-                format!(
-                    "Symbol path is empty, set it to something like \"{}\"\n",
-                    "srv*c:\\symbols*https://msdl.microsoft.com/download/symbols"
-                )
-                .as_str(),
-            );
-        } else {
-            self.log_text
-                .push_str(&format!("_NT_SYMBOL_PATH is ok: {:?}\n", symbol_path));
-            // split by * and ignore "srv" and "http" parts
-            let paths: Vec<&str> = symbol_path
-                .split('*')
-                .filter(|x| !x.starts_with("srv") && !x.starts_with("http"))
-                .collect();
-            // leave only first one, and warning if there are more
-            if paths.len() > 1 {
-                self.log_text.push_str(
-                    format!(
-                        "Warning: more than one path in _NT_SYMBOL_PATH, leaving only first one: {}\n",
-                        paths[0]
-                    ).as_str(),
-                );
-                self.symbol_path = paths[0].to_owned();
-            } else if paths.len() == 1 {
-                self.symbol_path = paths[0].to_owned();
-                self.log_text
-                    .push_str(format!("Symbol path for DR: {}\n", self.symbol_path).as_str());
-            } else {
-                self.log_text
-                    .push_str("Warning: no valid paths in _NT_SYMBOL_PATH found\n");
-            }
+            run_cmd: None,
+            run: None,
+            run_output: VecDeque::new(),
+            trace_view: TraceView::default(),
+            install_view: InstallView::default(),
+            catalogue_rc: None,
+            symbol_path_view: SymbolPathView::default(),
+            symstore_view: SymstoreView::default(),
+            symbols_rc: None,
+            symbol_view: SymbolView::default(),
         }
     }
 
     fn validate_fields_and_update_cmd(&mut self) {
         let mut is_update = false;
-        if self.settings.dr_dir != self.settings_cached.dr_dir {
+        // after switching profiles every field is checked again, even if it's the same value
+        let is_reload = std::mem::take(&mut self.is_settings_reloaded);
+        if is_reload || self.settings.dr_dir != self.settings_cached.dr_dir {
             self.is_dr_dir_ok = check_dr_dir(&self.settings.dr_dir);
             self.settings_cached.dr_dir = self.settings.dr_dir.clone();
             if self.is_dr_dir_ok {
@@ -199,7 +298,7 @@ impl MyApp {
             }
         }
 
-        if self.settings.dr_tool_path != self.settings_cached.dr_tool_path {
+        if is_reload || self.settings.dr_tool_path != self.settings_cached.dr_tool_path {
             self.is_dr_tool_path_ok = check_dr_tool_path(&self.settings.dr_tool_path);
             self.settings_cached.dr_tool_path = self.settings.dr_tool_path.clone();
             if self.is_dr_tool_path_ok {
@@ -216,7 +315,7 @@ impl MyApp {
             }
         }
 
-        if self.settings.inst_module != self.settings_cached.inst_module {
+        if is_reload || self.settings.inst_module != self.settings_cached.inst_module {
             self.settings_cached.inst_module = self.settings.inst_module.clone();
             self.log_text.push_str(&format!(
                 "Instrumentation module changed: {:?}\n",
@@ -225,7 +324,7 @@ impl MyApp {
             is_update = true;
         }
 
-        if self.settings.inst_mode != self.settings_cached.inst_mode {
+        if is_reload || self.settings.inst_mode != self.settings_cached.inst_mode {
             self.settings_cached.inst_mode = self.settings.inst_mode.clone();
             self.log_text.push_str(&format!(
                 "Instrumentation mode changed: {:?}\n",
@@ -234,7 +333,7 @@ impl MyApp {
             is_update = true;
         }
 
-        if self.settings.redirect_to_file != self.settings_cached.redirect_to_file {
+        if is_reload || self.settings.redirect_to_file != self.settings_cached.redirect_to_file {
             self.settings_cached.redirect_to_file = self.settings.redirect_to_file.clone();
             self.log_text.push_str(&format!(
                 "Redirect to file changed: {:?}\n",
@@ -243,58 +342,141 @@ impl MyApp {
             is_update = true;
         }
 
-        if self.settings.substr != self.settings_cached.substr {
+        if is_reload || self.settings.timeout_secs != self.settings_cached.timeout_secs {
+            self.settings_cached.timeout_secs = self.settings.timeout_secs;
+            self.log_text.push_str(&format!(
+                "Timeout changed: {} s\n",
+                self.settings.timeout_secs
+            ));
+            is_update = true;
+        }
+
+        if is_reload || self.settings.catalogue_url != self.settings_cached.catalogue_url {
+            self.settings_cached.catalogue_url = self.settings.catalogue_url.clone();
+            is_update = true;
+        }
+
+        if is_reload || self.settings.tools_dir != self.settings_cached.tools_dir {
+            self.settings_cached.tools_dir = self.settings.tools_dir.clone();
+            is_update = true;
+        }
+
+        if is_reload || self.settings.symbol_path != self.settings_cached.symbol_path {
+            self.settings_cached.symbol_path = self.settings.symbol_path.clone();
+            self.symbol_path = check_symbol_path(&self.settings.symbol_path, &mut self.log_text);
+            is_update = true;
+        }
+
+        if is_reload || self.settings.substr != self.settings_cached.substr {
             self.settings_cached.substr = self.settings.substr.clone();
             self.log_text
                 .push_str(&format!("Substring changed: {:?}\n", self.settings.substr));
             is_update = true;
         }
 
-        if self.settings.cmd != self.settings_cached.cmd {
+        if is_reload || self.settings.cmd != self.settings_cached.cmd {
             self.settings_cached.cmd = self.settings.cmd.clone();
             self.log_text.push_str(&format!(
                 "Target command line changed: {}\n",
                 self.settings.cmd
             ));
-            if self.settings.cmd.contains('"') {
-                self.is_quote_in_cmd = true;
-                self.log_text.push_str("Error: quote in command line\n");
+            if split_command_line(&self.settings.cmd).is_none() {
+                self.is_cmd_unbalanced = true;
+                self.log_text
+                    .push_str("Error: unbalanced quotes in command line\n");
             } else {
-                self.is_quote_in_cmd = false;
+                self.is_cmd_unbalanced = false;
             }
             is_update = true;
         }
 
         if is_update {
-            // --printSymsExec vs --printSymsInst
-            let str_mode = match self.settings.inst_mode {
-                DrToolInstrumentationMode::Exec => "--printSymsExec",
-                DrToolInstrumentationMode::Inst => "--printSymsInst",
-                DrToolInstrumentationMode::Invalid => todo!(),
-            };
-
-            self.cmd = format!(
-                "set _NT_SYMBOL_PATH={} && {}\\bin64\\drrun.exe -c {} {} --printSymsExecConsole --printSymsModule {}",
-                self.symbol_path,
-                self.settings.dr_dir,
-                self.settings.dr_tool_path,
-                str_mode,
-                self.settings.inst_module);
-
-            if self.settings.substr.len() > 0 {
-                self.cmd
-                    .push_str(&format!(" --printSymsGrep {}", self.settings.substr));
+            match DrRunCommand::new(&self.settings, &self.symbol_path) {
+                Ok(cmd) => {
+                    self.cmd = cmd.to_command_line();
+                    self.run_cmd = Some(cmd);
+                }
+                Err(e) => {
+                    self.cmd.clear();
+                    self.run_cmd = None;
+                    self.log_text.push_str(&format!("Error: {}\n", e));
+                }
             }
-
-            self.cmd.push_str(&format!(" -- {}", self.settings.cmd));
-
-            if !self.settings.redirect_to_file.is_empty() {
-                self.cmd
-                    .push_str(format!(" > {} 2>&1", self.settings.redirect_to_file).as_str());
+            self.profiles.set_current(self.settings.clone());
+            if let Err(e) = self.profiles.save() {
+                self.log_text.push_str(format!("Error: {}\n", e).as_str());
             }
+        }
+    }
 
-            self.settings.save();
+    /// Runs a profile action on top of the edited settings, then loads whatever profile is current.
+    fn update_profiles(&mut self, action: impl FnOnce(&mut Profiles) -> Result<(), String>) {
+        self.profiles.set_current(self.settings.clone());
+        if let Err(e) = action(&mut self.profiles) {
+            self.log_text.push_str(format!("Error: {}\n", e).as_str());
+            return;
         }
+        if let Err(e) = self.profiles.save() {
+            self.log_text.push_str(format!("Error: {}\n", e).as_str());
+        }
+        self.settings = self.profiles.current().clone();
+        self.is_settings_reloaded = true;
+        self.log_text
+            .push_str(format!("Profile: {}\n", self.profiles.current).as_str());
+    }
+
+    fn show_profile_row(&mut self, ui: &mut egui::Ui) {
+        ui.label("Profile");
+        ui.horizontal(|ui| {
+            let mut selected = self.profiles.current.clone();
+            egui::ComboBox::from_id_source("profile")
+                .selected_text(&selected)
+                .show_ui(ui, |ui| {
+                    for name in self.profiles.profiles.keys() {
+                        ui.selectable_value(&mut selected, name.clone(), name);
+                    }
+                })
+                .response
+                .on_hover_text(format!("Stored in {}", self.profiles.path().display()));
+            if selected != self.profiles.current {
+                self.update_profiles(|p| p.select(&selected));
+            }
+            ui.add(
+                egui::TextEdit::singleline(&mut self.profile_name)
+                    .hint_text("Profile name")
+                    .desired_width(120.0),
+            );
+            let name = self.profile_name.trim().to_owned();
+            if ui
+                .button("➕")
+                .on_hover_text("Create a profile with default settings")
+                .clicked()
+            {
+                self.update_profiles(|p| p.create(&name, Settings::default()));
+            }
+            if ui
+                .button("🗐")
+                .on_hover_text("Duplicate the current profile")
+                .clicked()
+            {
+                self.update_profiles(|p| p.create(&name, p.current().clone()));
+            }
+            if ui
+                .button("✏")
+                .on_hover_text("Rename the current profile")
+                .clicked()
+            {
+                self.update_profiles(|p| p.rename_current(&name));
+            }
+            if ui
+                .button("🗑")
+                .on_hover_text("Delete the current profile")
+                .clicked()
+            {
+                self.update_profiles(|p| p.delete_current());
+            }
+        });
+        ui.end_row();
     }
 
     fn show_dr_dir_row(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
@@ -304,11 +486,11 @@ impl MyApp {
         ui.horizontal(|ui| {
             ui.set_width(ui.available_width());
             let dir_input = egui::TextEdit::singleline(&mut self.settings.dr_dir);
-            ui.add_enabled(!self.is_dr_download_started, dir_input);
+            ui.add_enabled(self.dr_download.is_none(), dir_input);
 
             let open_dir = egui::Button::new("📁🔍");
             if ui
-                .add_enabled(!self.is_dr_download_started, open_dir)
+                .add_enabled(self.dr_download.is_none(), open_dir)
                 .on_hover_text("Open directory dialog")
                 .clicked()
             {
@@ -322,67 +504,386 @@ impl MyApp {
                     self.log_text.push_str("No valid directory selected\n");
                 }
             }
-            let down_button = egui::Button::new("🌐⬇");
             if ui
-                .add_enabled(!self.is_dr_download_started, down_button)
-                .on_hover_text(format!(
-                    "Download from the internet to specified directory.\nUrl: {}",
-                    DR_DOWNLOAD_URL
-                ))
+                .button("🌐⬇")
+                .on_hover_text("Install a DynamoRIO release into the tools dir")
                 .clicked()
             {
-                // check if there is already a valid DR path
-                if self.is_dr_dir_ok {
-                    self.log_text.push_str("DR dir is already valid\n");
-                    return;
+                self.install_view.open = true;
+                if self.install_view.catalogue.is_none() {
+                    self.reload_install_view(ctx);
+                }
+            }
+            if let Some(download) = &self.dr_download {
+                download.show(ui);
+            }
+            if let (Some(e), Some((release, sha256))) =
+                (&self.dr_download_error, &self.dr_download_release)
+            {
+                if show_download_error(ui, e) {
+                    self.start_dr_install(release.clone(), sha256.clone(), ctx);
+                }
+            }
+            if !self.is_dr_dir_ok {
+                ui.colored_label(egui::Color32::RED, "☹")
+                    .on_hover_text("Invalid directory");
+            }
+        });
+
+        ui.end_row();
+    }
+
+    /// Loads the catalogue on a background thread, it may come from the network.
+    fn reload_install_view(&mut self, ctx: &egui::Context) {
+        let tools_dir = tools_dir(&self.settings.tools_dir);
+        match Installed::load(&tools_dir) {
+            Ok(installed) => self.install_view.installed = installed,
+            Err(e) => self.log_text.push_str(format!("Error: {}\n", e).as_str()),
+        }
+        let (tx, rc) = mpsc::channel();
+        let source = self.settings.catalogue_url.clone();
+        let ctx2 = ctx.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(Catalogue::load(&source));
+            ctx2.request_repaint();
+        });
+        self.catalogue_rc = Some(rc);
+    }
+
+    fn start_dr_install(&mut self, release: Release, sha256: Option<String>, ctx: &egui::Context) {
+        let tools_dir = tools_dir(&self.settings.tools_dir);
+        self.log_text.push_str(
+            format!(
+                "Installing DynamoRIO {} into {} ...\n",
+                release.version,
+                tools_dir.display()
+            )
+            .as_str(),
+        );
+        let download = DownloadState::new();
+        let cancel = download.cancel.clone();
+        self.dr_download = Some(download);
+        self.dr_download_error = None;
+        self.dr_download_release = Some((release.clone(), sha256.clone()));
+        let tx = self.on_done_dr_down_tx.clone();
+        let ctx2 = ctx.clone();
+        std::thread::spawn(move || {
+            let result = install_dr(
+                &release,
+                &tools_dir,
+                sha256.as_deref(),
+                &cancel,
+                &mut |event| {
+                    let _ = tx.send(event);
+                    ctx2.request_repaint();
+                },
+            );
+            let _ = tx.send(DownloadEvent::Done(
+                result.map(|dir| dir.display().to_string()),
+            ));
+            ctx2.request_repaint();
+        });
+    }
+
+    fn show_install_view(&mut self, ctx: &egui::Context) {
+        let action = self
+            .install_view
+            .show(ctx, &mut self.settings, self.dr_download.as_ref());
+        match action {
+            Some(InstallAction::Reload) => self.reload_install_view(ctx),
+            Some(InstallAction::Install(release, sha256)) => {
+                self.start_dr_install(release, sha256, ctx)
+            }
+            Some(InstallAction::Use(dir)) => self.settings.dr_dir = dir,
+            None => {}
+        }
+        if let Some(Ok(catalogue)) = self.catalogue_rc.as_ref().map(|rc| rc.try_recv()) {
+            self.catalogue_rc = None;
+            match catalogue {
+                Ok(catalogue) => self.install_view.catalogue = Some(catalogue),
+                Err(e) => self
+                    .log_text
+                    .push_str(format!("Error: can't load the catalogue: {}\n", e).as_str()),
+            }
+        }
+    }
+
+    fn symbol_stores(&self) -> Vec<String> {
+        split_stores(&self.symbol_path)
+    }
+
+    fn start_symbol_check(&mut self, ctx: &egui::Context) {
+        self.log_text
+            .push_str(format!("Checking symbols of {} ...\n", self.settings.inst_module).as_str());
+        let (tx, rc) = mpsc::channel();
+        let settings = self.settings.clone();
+        let stores = self.symbol_stores();
+        let ctx2 = ctx.clone();
+        // big PDBs take a while
+        std::thread::spawn(move || {
+            let _ = tx.send(module_symbols(&settings, &stores));
+            ctx2.request_repaint();
+        });
+        self.symbols_rc = Some(rc);
+    }
+
+    fn poll_symbol_check(&mut self) {
+        let Some(Ok(result)) = self.symbols_rc.as_ref().map(|rc| rc.try_recv()) else {
+            return;
+        };
+        self.symbols_rc = None;
+        let symbols = match result {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                self.log_text.push_str(format!("Error: {}\n", e).as_str());
+                return;
+            }
+        };
+        self.log_text.push_str(
+            format!(
+                "Symbols of {} from {}: {} functions\n",
+                symbols.module.display(),
+                symbols.debug_file.display(),
+                symbols.functions.len()
+            )
+            .as_str(),
+        );
+        if let Some(warning) = symbols.warning() {
+            self.log_text
+                .push_str(format!("Warning: {}\n", warning).as_str());
+        }
+        if symbols.functions.is_empty() {
+            return;
+        }
+        if !self.settings.substr.is_empty() {
+            let count = matching(&symbols.functions, &self.settings.substr).count();
+            self.log_text
+                .push_str(format!("{} match {:?}\n", count, self.settings.substr).as_str());
+        }
+        self.symbol_view.set(symbols);
+    }
+
+    fn start_run(&mut self, ctx: &egui::Context) {
+        let Some(cmd) = &self.run_cmd else {
+            self.log_text.push_str("Error: no valid command line\n");
+            return;
+        };
+        self.log_text
+            .push_str(format!("Running: {}\n", self.cmd).as_str());
+        let ctx2 = ctx.clone();
+        match runner::start(cmd, self.settings.timeout(), move || ctx2.request_repaint()) {
+            Ok(run) => {
+                self.run_output.clear();
+                self.run = Some(run);
+            }
+            Err(e) => self.log_text.push_str(format!("Error: {}\n", e).as_str()),
+        }
+    }
+
+    fn poll_run(&mut self) {
+        let Some(run) = &self.run else {
+            return;
+        };
+        let mut is_done = false;
+        while let Ok(event) = run.rx.try_recv() {
+            match event {
+                RunEvent::Stdout(line) => self.run_output.push_back((false, line)),
+                RunEvent::Stderr(line) => self.run_output.push_back((true, line)),
+                RunEvent::Stopped(StopReason::User) => {
+                    self.log_text.push_str("Stopped by user\n");
+                }
+                RunEvent::Stopped(StopReason::Timeout) => {
+                    self.log_text.push_str(
+                        format!("Timed out after {} s\n", self.settings.timeout_secs).as_str(),
+                    );
+                }
+                RunEvent::Exited(Ok(exit_code)) => {
+                    self.log_text
+                        .push_str(format!("Exit code: {}\n", exit_code).as_str());
+                    is_done = true;
+                }
+                RunEvent::Exited(Err(e)) => {
+                    self.log_text.push_str(format!("Error: {}\n", e).as_str());
+                    is_done = true;
                 }
+            }
+        }
+        while self.run_output.len() > MAX_OUTPUT_LINES {
+            self.run_output.pop_front();
+        }
+        if is_done {
+            self.run = None;
+            // refresh the trace window with the new run
+            if self.trace_view.open {
+                self.load_trace();
+            }
+        }
+    }
+
+    fn load_trace(&mut self) {
+        if self.settings.redirect_to_file.is_empty() {
+            self.log_text
+                .push_str("No redirect file set, there is no trace to show\n");
+            return;
+        }
+        let path = self.settings.redirect_to_file.clone();
+        match self.trace_view.load(&path) {
+            Ok(()) => self
+                .log_text
+                .push_str(format!("Trace loaded: {}\n", path).as_str()),
+            Err(e) => self.log_text.push_str(format!("Error: {}\n", e).as_str()),
+        }
+    }
 
-                // if input directory is a valid directory use it as a destination directory
-                // otherwise pick a directory, if that is failed, use use current directory
-                let dest_dir: String;
-                // check if directory is valid, othervise open file dialog
-                if !Path::new(&self.settings.dr_dir).exists() {
-                    // get current directory
-                    let current_dir = std::env::current_dir().unwrap();
-                    // open file dialog
-                    let fd = rfd::FileDialog::new().set_directory(&current_dir);
-                    if let Some(result) = fd.pick_folder() {
-                        dest_dir = result.display().to_string();
+    fn export_trace(&mut self, ext: &str, export_fn: fn(&str, &str) -> Result<(), String>) {
+        let trace = self.settings.redirect_to_file.clone();
+        if trace.is_empty() {
+            self.log_text
+                .push_str("No redirect file set, there is no trace to export\n");
+            return;
+        }
+        let file_name = format!(
+            "{}.{}",
+            Path::new(&trace)
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            ext
+        );
+        let current_dir = std::env::current_dir().unwrap();
+        let fd = rfd::FileDialog::new()
+            .set_directory(&current_dir)
+            .set_file_name(&file_name);
+        let Some(out) = fd.save_file() else {
+            self.log_text.push_str("No valid file selected\n");
+            return;
+        };
+        let out = out.display().to_string();
+        match export_fn(&trace, &out) {
+            Ok(()) => self
+                .log_text
+                .push_str(format!("Exported {} -> {}\n", trace, out).as_str()),
+            Err(e) => self.log_text.push_str(format!("Error: {}\n", e).as_str()),
+        }
+    }
+
+    fn show_output(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.heading("Output");
+            if ui.button("🗑").on_hover_text("Clear output").clicked() {
+                self.run_output.clear();
+            };
+            if ui
+                .button("🗐")
+                .on_hover_text("Copy output to clipboard")
+                .clicked()
+            {
+                let text = self
+                    .run_output
+                    .iter()
+                    .map(|(_, line)| line.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                ctx.output_mut(|o| o.copied_text = text);
+            };
+        });
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::both()
+            .id_source("output")
+            .max_height(150.0)
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, self.run_output.len(), |ui, rows| {
+                ui.set_width(ui.available_width());
+                for (is_stderr, line) in self.run_output.range(rows) {
+                    let text = egui::RichText::new(line).monospace();
+                    if *is_stderr {
+                        ui.colored_label(egui::Color32::LIGHT_RED, text);
                     } else {
-                        dest_dir = current_dir.display().to_string();
+                        ui.label(text);
                     }
-                } else {
-                    dest_dir = self.settings.dr_dir.clone();
                 }
+            });
+    }
 
-                self.is_dr_download_started = true;
-                let tx = self.on_done_dr_down_tx.clone();
-                let ctx2 = ctx.clone();
-                self.log_text.push_str(
-                    format!("Download started {} -> {} ...\n", DR_DOWNLOAD_URL, dest_dir).as_str(),
-                );
-                std::thread::spawn(move || {
-                    let mut resp = reqwest::blocking::get(DR_DOWNLOAD_URL).unwrap();
-                    assert!(resp.status().is_success());
-                    let last_part = DR_DOWNLOAD_URL.split('/').last().unwrap();
-                    let last_part_no_zip = last_part.split(".zip").next().unwrap();
-                    let mut out = std::fs::File::create(last_part).unwrap();
-                    resp.copy_to(&mut out).unwrap();
-                    extract_zip_to_dir(last_part, dest_dir.as_str());
-                    ctx2.request_repaint();
-                    let dest_dir = Path::new(&dest_dir).join(last_part_no_zip);
-                    let _ = tx.send(Some(dest_dir.display().to_string().to_owned()));
+    fn start_tool_download(
+        &mut self,
+        dest_dir: String,
+        sha256: Option<String>,
+        ctx: &egui::Context,
+    ) {
+        let download = DownloadState::new();
+        let cancel = download.cancel.clone();
+        self.tool_download = Some(download);
+        self.tool_download_error = None;
+        self.tool_download_dir = dest_dir.clone();
+        self.tool_download_sha256 = sha256.clone();
+        let tx = self.on_done_tool_down_tx.clone();
+        let ctx2 = ctx.clone();
+        let catalogue_url = self.settings.catalogue_url.clone();
+        let tools_dir = tools_dir(&self.settings.tools_dir);
+        log("spawning a thread\n");
+        self.log_text
+            .push_str(format!("Download started -> {} ...\n", dest_dir).as_str());
+        std::thread::spawn(move || {
+            log("thread started\n");
+            let mut notify = |event| {
+                let _ = tx.send(event);
+                ctx2.request_repaint();
+            };
+            let result = Catalogue::load(&catalogue_url)
+                .map_err(DownloadError::Install)
+                .and_then(|catalogue| {
+                    install_tool(
+                        &catalogue,
+                        &tools_dir,
+                        Path::new(&dest_dir),
+                        sha256.as_deref(),
+                        &cancel,
+                        &mut notify,
+                    )
                 });
+            log("file written\n");
+
+            notify(DownloadEvent::Done(
+                result.map(|path| path.display().to_string()),
+            ));
+        });
+    }
+
+    fn show_symbol_path_row(&mut self, ui: &mut egui::Ui) {
+        ui.label("Symbol path");
+        ui.horizontal(|ui| {
+            let env = std::env::var(SYMBOL_PATH_ENV).unwrap_or_default();
+            ui.add(
+                egui::TextEdit::singleline(&mut self.settings.symbol_path)
+                    .hint_text(format!("{}: {}", SYMBOL_PATH_ENV, env)),
+            )
+            .on_hover_text(format!(
+                "{} syntax, the environment variable is used if it's empty.\n\
+                 Local stores are passed to DynamoRIO: {}",
+                SYMBOL_PATH_ENV, self.symbol_path
+            ));
+            if ui
+                .button("✏")
+                .on_hover_text("Edit the stores and servers")
+                .clicked()
+            {
+                self.symbol_path_view.open = true;
             }
-            if self.is_dr_download_started {
-                ui.spinner();
+            if ui
+                .button("🗄")
+                .on_hover_text("Symbol store: list and import PDBs, check the module's one")
+                .clicked()
+            {
+                self.symstore_view.open = true;
+                self.symstore_view.is_stale = true;
             }
-            if !self.is_dr_dir_ok {
+            if self.symbol_path.is_empty() {
                 ui.colored_label(egui::Color32::RED, "☹")
-                    .on_hover_text("Invalid directory");
+                    .on_hover_text("No local symbol stores, see the log");
             }
         });
-
         ui.end_row();
     }
 
@@ -392,10 +893,10 @@ impl MyApp {
         });
         ui.horizontal(|ui| {
             let dir_input = egui::TextEdit::singleline(&mut self.settings.dr_tool_path);
-            ui.add_enabled(!self.is_dr_tool_download_started, dir_input);
+            ui.add_enabled(self.tool_download.is_none(), dir_input);
             let open_dir = egui::Button::new("📁🔍");
             if ui
-                .add_enabled(!self.is_dr_tool_download_started, open_dir)
+                .add_enabled(self.tool_download.is_none(), open_dir)
                 .on_hover_text("Find file dialog")
                 .clicked()
             {
@@ -404,20 +905,31 @@ impl MyApp {
                 // open file dialog
                 let fd = rfd::FileDialog::new()
                     .set_directory(&current_dir)
-                    .add_filter("DynamoRIO tool (DrSymLogger.dll)", &["dll"]);
+                    .add_filter(
+                        &format!("DynamoRIO tool ({})", DR_TOOL_NAME),
+                        &[DR_TOOL_NAME.rsplit('.').next().unwrap()],
+                    );
                 if let Some(result) = fd.pick_file() {
                     self.settings.dr_tool_path = result.display().to_string();
                 } else {
                     self.log_text.push_str("No valid file selected\n");
                 }
             }
+            ui.add_enabled(
+                self.tool_download.is_none(),
+                egui::TextEdit::singleline(&mut self.tool_sha256)
+                    .hint_text("SHA-256")
+                    .desired_width(80.0),
+            )
+            .on_hover_text(
+                "Expected checksum of the tool to download next, the catalogue's one if empty",
+            );
             let down_button = egui::Button::new("🌐⬇");
             if ui
-                .add_enabled(!self.is_dr_tool_download_started, down_button)
-                .on_hover_text(format!(
-                    "Download from the internet to specified directory.\nUrl: {}",
-                    DR_TOOL_DOWNLOAD_URL
-                ))
+                .add_enabled(self.tool_download.is_none(), down_button)
+                .on_hover_text(
+                    "Download the DrSymLogger release from the catalogue to specified directory",
+                )
                 .clicked()
             {
                 if self.is_dr_tool_path_ok {
@@ -434,34 +946,21 @@ impl MyApp {
                     dest_dir = result.display().to_string();
                 }
 
-                self.is_dr_tool_download_started = true;
-                let tx = self.on_done_tool_down_tx.clone();
-                let ctx2 = ctx.clone();
-                log("spawning a thread\n");
-                self.log_text.push_str(
-                    format!(
-                        "Download started {} -> {} ...\n",
-                        DR_TOOL_DOWNLOAD_URL, dest_dir
-                    )
-                    .as_str(),
-                );
-                std::thread::spawn(move || {
-                    log("thread started\n");
-                    let mut resp = reqwest::blocking::get(DR_TOOL_DOWNLOAD_URL).unwrap();
-                    log("get done\n");
-                    assert!(resp.status().is_success());
-                    let last_part = DR_TOOL_DOWNLOAD_URL.split('/').last().unwrap();
-                    let mut out = std::fs::File::create(last_part).unwrap();
-                    resp.copy_to(&mut out).unwrap();
-                    log("file written\n");
-
-                    ctx2.request_repaint();
-                    let dest_dir = Path::new(&dest_dir).join(last_part);
-                    let _ = tx.send(Some(dest_dir.display().to_string().to_owned()));
-                });
+                let sha256 = self.tool_sha256.trim();
+                let sha256 = (!sha256.is_empty()).then(|| sha256.to_owned());
+                self.start_tool_download(dest_dir, sha256, ctx);
             }
-            if self.is_dr_tool_download_started {
-                ui.spinner();
+            if let Some(download) = &self.tool_download {
+                download.show(ui);
+            }
+            if let Some(e) = &self.tool_download_error {
+                if show_download_error(ui, e) {
+                    self.start_tool_download(
+                        self.tool_download_dir.clone(),
+                        self.tool_download_sha256.clone(),
+                        ctx,
+                    );
+                }
             }
             if !self.is_dr_tool_path_ok {
                 ui.colored_label(egui::Color32::RED, "☹")
@@ -488,8 +987,10 @@ impl eframe::App for MyApp {
                 .num_columns(2)
                 .striped(true)
                 .show(ui, |ui| {
+                    self.show_profile_row(ui);
                     self.show_dr_dir_row(ui, ctx);
                     self.show_tool_path_row(ui, ctx);
+                    self.show_symbol_path_row(ui);
 
                     ui.label("Command line");
                     ui.horizontal(|ui| {
@@ -498,9 +999,9 @@ impl eframe::App for MyApp {
                             ui.colored_label(egui::Color32::RED, "☹")
                                 .on_hover_text("Command line can't be empty");
                         }
-                        if self.is_quote_in_cmd {
+                        if self.is_cmd_unbalanced {
                             ui.colored_label(egui::Color32::RED, "☹")
-                                .on_hover_text("No quotes currently supported, use console please");
+                                .on_hover_text("Unbalanced quotes in command line");
                         }
                     });
                     ui.end_row();
@@ -539,6 +1040,12 @@ impl eframe::App for MyApp {
                         ui.text_edit_singleline(&mut self.settings.redirect_to_file)
                     });
                     ui.end_row();
+
+                    ui.label("Timeout, seconds (0 = none)");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut self.settings.timeout_secs))
+                    });
+                    ui.end_row();
                 });
 
             ui.separator();
@@ -552,64 +1059,51 @@ impl eframe::App for MyApp {
                     ctx.output_mut(|o| o.copied_text = self.cmd.clone());
                 };
                 if ui
-                    .button("Run cdb")
-                    .on_hover_text("Run cdb.exe to check symbols availability")
+                    .add_enabled(
+                        self.symbols_rc.is_none(),
+                        egui::Button::new("Check symbols"),
+                    )
+                    .on_hover_text(
+                        "Read the module's functions from its PDB in the symbol stores, or from \
+                         its ELF symbol tables, DWARF and debug file, and browse them",
+                    )
                     .clicked()
                 {
-                    let cdb_path =
-                        "C:\\Program Files (x86)\\Windows Kits\\10\\Debuggers\\x64\\cdb.exe";
-                    // split ext from module
-                    let mut module = self.settings.inst_module.clone();
-                    let mut _ext = "";
-                    if let Some(pos) = module.rfind('.') {
-                        _ext = &module[pos..];
-                        module = module[..pos].to_string();
-                    }
-                    // if path exist
-                    let cmd_args = format!("-c \"x {}!*\" {}", module, self.settings.cmd);
-                    self.log_text
-                        .push_str(format!("Running: {} {}\n", cdb_path, cmd_args).as_str());
-                    // I didn't find a way to pass args as a single string, so I split it
-                    // into a vector respecting quotes
-                    let args_vec = Shlex::new(&cmd_args).collect::<Vec<_>>();
-                    let child = std::process::Command::new(cdb_path).args(&args_vec).spawn();
-                    if let Err(e) = child {
-                        self.log_text.push_str(format!("Error: {}\n", e).as_str());
-                    } else {
-                        let exit_code = child.unwrap().wait().unwrap();
-                        self.log_text
-                            .push_str(format!("Exit code: {}\n", exit_code).as_str());
-                    }
+                    self.start_symbol_check(ctx);
                 }
+                let run_button = egui::Button::new("Run");
                 if ui
-                    .button("Run")
+                    .add_enabled(self.run.is_none(), run_button)
                     .on_hover_text("Run the target process")
                     .clicked()
                 {
-                    // replace new line with &&
-                    let cmd = self.cmd.replace("\n", "&&");
-                    //let cmd = cmd.replace("\"", "\"\"");
-                    self.log_text
-                        .push_str(format!("Running: {}\n", cmd).as_str());
-                    // run the process
-                    let child = std::process::Command::new("cmd")
-                        .args(&["/c", &cmd])
-                        .spawn();
-                    // check if error
-                    if let Err(e) = child {
-                        self.log_text.push_str(format!("Error: {}\n", e).as_str());
-                    } else {
-                        let exit_code = child.unwrap().wait().unwrap();
-                        self.log_text
-                            .push_str(format!("Exit code: {}\n", exit_code).as_str());
-                    }
+                    self.start_run(ctx);
                 };
+                if ui
+                    .button("🌲")
+                    .on_hover_text("Show the call trace from the redirect file")
+                    .clicked()
+                {
+                    self.load_trace();
+                }
+                if let Some(run) = &self.run {
+                    if ui
+                        .button("Stop")
+                        .on_hover_text("Kill the target and all its child processes")
+                        .clicked()
+                    {
+                        run.stop();
+                    }
+                    ui.spinner();
+                }
             });
             ui.horizontal(|ui| {
                 ui.style_mut().wrap = Some(true);
                 ui.label(&self.cmd);
             });
 
+            self.show_output(ui, ctx);
+
             ui.separator();
             ui.horizontal(|ui| {
                 ui.heading("Log");
@@ -623,6 +1117,26 @@ impl eframe::App for MyApp {
                 {
                     ctx.output_mut(|o| o.copied_text = self.log_text.clone());
                 };
+                if ui
+                    .button("⤓ Chrome trace")
+                    .on_hover_text(
+                        "Export the trace from the redirect file to Trace Event JSON\n\
+                         (chrome://tracing, Perfetto)",
+                    )
+                    .clicked()
+                {
+                    self.export_trace("json", export::export_chrome_trace);
+                };
+                if ui
+                    .button("⤓ Folded")
+                    .on_hover_text(
+                        "Export the trace from the redirect file to folded stacks\n\
+                         (inferno, flamegraph.pl)",
+                    )
+                    .clicked()
+                {
+                    self.export_trace("folded", export::export_folded);
+                };
             });
             egui::ScrollArea::both()
                 .stick_to_bottom(true)
@@ -634,14 +1148,60 @@ impl eframe::App for MyApp {
                         ui.label(line);
                     }
                 });
+            self.trace_view.show(ctx);
+            self.symbol_view.show(ctx, &mut self.settings.substr);
+            self.show_install_view(ctx);
+            self.symbol_path_view
+                .show(ctx, &mut self.settings.symbol_path);
+            let stores = self.symbol_stores();
+            self.symstore_view
+                .show(ctx, &self.settings, &stores, &mut self.log_text);
             // check if spawned thread sent data
-            if let Ok(data) = self.on_done_dr_down_rc.try_recv() {
-                self.settings.dr_dir = data.unwrap();
-                self.is_dr_download_started = false;
+            self.poll_run();
+            self.poll_symbol_check();
+            let dr_result = poll_download(
+                &self.on_done_dr_down_rc,
+                &mut self.dr_download,
+                &mut self.log_text,
+            );
+            if let Some(result) = dr_result {
+                match result {
+                    Ok(dir) => {
+                        self.log_text
+                            .push_str(format!("DynamoRIO installed: {}\n", dir).as_str());
+                        self.settings.dr_dir = dir;
+                        self.reload_install_view(ctx);
+                    }
+                    Err(DownloadError::Cancelled) => {
+                        self.log_text.push_str("DynamoRIO install cancelled\n")
+                    }
+                    Err(e) => {
+                        self.log_text
+                            .push_str(format!("Error: DynamoRIO install failed: {}\n", e).as_str());
+                        self.dr_download_error = Some(e);
+                    }
+                }
             }
-            if let Ok(data) = self.on_done_tool_down_rc.try_recv() {
-                self.settings.dr_tool_path = data.unwrap();
-                self.is_dr_tool_download_started = false;
+            let tool_result = poll_download(
+                &self.on_done_tool_down_rc,
+                &mut self.tool_download,
+                &mut self.log_text,
+            );
+            match tool_result {
+                Some(Ok(path)) => {
+                    self.log_text
+                        .push_str(format!("Downloaded: {}\n", path).as_str());
+                    self.settings.dr_tool_path = path;
+                }
+                Some(Err(DownloadError::Cancelled)) => {
+                    self.log_text.push_str("Download cancelled\n")
+                }
+                Some(Err(e)) => {
+                    self.log_text
+                        .push_str(format!("Error: download failed: {}\n", e).as_str());
+                    self.tool_download_error = Some(e);
+                }
+                None => {}
             }
         });
     }
@@ -771,60 +1331,3 @@ fn close_maximize_minimize(ui: &mut egui::Ui, frame: &mut eframe::Frame) {
         frame.set_minimized(true);
     }
 }
-
-fn check_dr_dir(dr_dir: &str) -> bool {
-    let dr_dir = Path::new(dr_dir);
-    if !dr_dir.exists() {
-        return false;
-    }
-    let dr_dir = dr_dir.join("bin64");
-    if !dr_dir.exists() {
-        return false;
-    }
-    let dr_dir = dr_dir.join("drrun.exe");
-    if !dr_dir.exists() {
-        return false;
-    }
-    true
-}
-
-fn check_dr_tool_path(dr_tool_path: &str) -> bool {
-    // check if path exists, and the file name name is DrSymLogger.dll
-    let dr_tool_path = Path::new(dr_tool_path);
-    if !dr_tool_path.exists() {
-        return false;
-    }
-    let dr_tool_path = dr_tool_path.file_name().unwrap();
-    if dr_tool_path != "DrSymLogger.dll" {
-        return false;
-    }
-    true
-}
-
-fn extract_zip_to_dir(input_path: &str, dest_dir: &str) {
-    let file = std::fs::File::open(input_path).unwrap();
-    let mut archive = zip::ZipArchive::new(file).unwrap();
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
-        let outpath = Path::new(dest_dir).join(file.mangled_name());
-        if (&*file.name()).ends_with('/') {
-            log(format!("File {} extracted to \"{}\"\n", i, outpath.display()).as_str());
-            std::fs::create_dir_all(&outpath).unwrap();
-        } else {
-            log(format!(
-                "File {} extracted to \"{}\" ({} bytes)\n",
-                i,
-                outpath.display(),
-                file.size()
-            )
-            .as_str());
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    std::fs::create_dir_all(&p).unwrap();
-                }
-            }
-            let mut outfile = std::fs::File::create(&outpath).unwrap();
-            std::io::copy(&mut file, &mut outfile).unwrap();
-        }
-    }
-}
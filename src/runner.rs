@@ -0,0 +1,281 @@
+// Runs the instrumented target on background threads and streams its output back line by line.
+
+use crate::command::DrRunCommand;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub enum RunEvent {
+    Stdout(String),
+    Stderr(String),
+    /// The process tree was killed, `Exited` follows once it's reaped.
+    Stopped(StopReason),
+    Exited(Result<ExitStatus, String>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StopReason {
+    User,
+    Timeout,
+}
+
+pub struct RunHandle {
+    pub rx: mpsc::Receiver<RunEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl RunHandle {
+    /// Asks the watcher thread to kill the whole process tree.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Spawns the command with piped stdout/stderr. Every line is sent over the returned channel
+/// and also written to the redirect file, so the trace ends up on disk as before.
+/// The process tree is killed when `timeout` elapses or `RunHandle::stop` is called.
+/// `notify` is called after each event, the GUI uses it to request a repaint.
+pub fn start(
+    cmd: &DrRunCommand,
+    timeout: Option<Duration>,
+    notify: impl Fn() + Send + Sync + 'static,
+) -> std::io::Result<RunHandle> {
+    let redirect = match &cmd.redirect {
+        Some(path) => Some(Arc::new(Mutex::new(std::fs::File::create(path)?))),
+        None => None,
+    };
+    let mut command = cmd.command();
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // own process group, so the whole tree can be killed at once
+        command.process_group(0);
+    }
+    let mut child = command.spawn()?;
+
+    let (tx, rx) = mpsc::channel();
+    let notify = Arc::new(notify);
+    let readers = vec![
+        spawn_reader(
+            child.stdout.take().unwrap(),
+            RunEvent::Stdout,
+            tx.clone(),
+            redirect.clone(),
+            notify.clone(),
+        ),
+        spawn_reader(
+            child.stderr.take().unwrap(),
+            RunEvent::Stderr,
+            tx.clone(),
+            redirect,
+            notify.clone(),
+        ),
+    ];
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop2 = stop.clone();
+    let deadline = timeout.map(|t| Instant::now() + t);
+    std::thread::spawn(move || {
+        let mut is_killed = false;
+        let mut status = None;
+        loop {
+            if status.is_none() {
+                match child.try_wait() {
+                    Ok(Some(exited)) => status = Some(Ok(exited)),
+                    Ok(None) => {}
+                    Err(e) => status = Some(Err(e.to_string())),
+                }
+            }
+            // a background process may keep the pipes open after drrun exits, it can still be
+            // stopped then, the process group outlives its leader
+            if status.is_some() && readers.iter().all(|reader| reader.is_finished()) {
+                break;
+            }
+            if !is_killed {
+                let reason = if stop2.load(Ordering::SeqCst) {
+                    Some(StopReason::User)
+                } else if deadline.map_or(false, |d| Instant::now() >= d) {
+                    Some(StopReason::Timeout)
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    kill_tree(&mut child);
+                    is_killed = true;
+                    let _ = tx.send(RunEvent::Stopped(reason));
+                    notify();
+                }
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        // the pipes are drained, so the exit event is always the last one
+        for reader in readers {
+            let _ = reader.join();
+        }
+        let _ = tx.send(RunEvent::Exited(status.unwrap()));
+        notify();
+    });
+    Ok(RunHandle { rx, stop })
+}
+
+#[cfg(windows)]
+fn kill_tree(child: &mut Child) {
+    // drrun starts the target as a separate process, taskkill /t takes the children too
+    let killed = std::process::Command::new("taskkill")
+        .args(["/t", "/f", "/pid", &child.id().to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_or(false, |s| s.success());
+    if !killed {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(unix)]
+fn kill_tree(child: &mut Child) {
+    extern "C" {
+        fn kill(pid: i32, sig: i32) -> i32;
+    }
+    const SIGKILL: i32 = 9;
+    // negative pid addresses the process group created in start()
+    if unsafe { kill(-(child.id() as i32), SIGKILL) } != 0 {
+        let _ = child.kill();
+    }
+}
+
+fn spawn_reader(
+    pipe: impl Read + Send + 'static,
+    event: fn(String) -> RunEvent,
+    tx: mpsc::Sender<RunEvent>,
+    redirect: Option<Arc<Mutex<std::fs::File>>>,
+    notify: Arc<impl Fn() + Send + Sync + 'static>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if let Some(file) = &redirect {
+                let _ = file.lock().unwrap().write_all(&buf);
+            }
+            // the target's output isn't necessarily utf-8
+            let line = String::from_utf8_lossy(&buf)
+                .trim_end_matches(['\r', '\n'])
+                .to_owned();
+            // if nobody listens anymore keep draining anyway, so the target doesn't block
+            if tx.send(event(line)).is_ok() {
+                notify();
+            }
+        }
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_lines_and_exit_code() {
+        let redirect =
+            std::env::temp_dir().join(format!("runner_stream_{}.txt", std::process::id()));
+        let cmd = DrRunCommand {
+            program: "sh".to_owned(),
+            env: vec![("DR_TEST_VAR".to_owned(), "one".to_owned())],
+            dr_args: vec![
+                "-c".to_owned(),
+                "echo $DR_TEST_VAR; echo two >&2; echo three; exit 5".to_owned(),
+            ],
+            tool_args: vec![],
+            target_args: vec![],
+            redirect: Some(redirect.display().to_string()),
+        };
+        let handle = start(&cmd, None, || {}).unwrap();
+        let mut stdout = vec![];
+        let mut stderr = vec![];
+        let mut status = None;
+        for event in handle.rx {
+            match event {
+                RunEvent::Stdout(line) => stdout.push(line),
+                RunEvent::Stderr(line) => stderr.push(line),
+                RunEvent::Stopped(_) => panic!("unexpected stop"),
+                RunEvent::Exited(s) => status = Some(s.unwrap()),
+            }
+        }
+        assert_eq!(stdout, vec!["one", "three"]);
+        assert_eq!(stderr, vec!["two"]);
+        assert_eq!(status.unwrap().code(), Some(5));
+        let file = std::fs::read_to_string(&redirect).unwrap();
+        std::fs::remove_file(&redirect).unwrap();
+        assert_eq!(file.lines().count(), 3);
+    }
+
+    fn sleeper() -> DrRunCommand {
+        // the child sleep keeps the pipes open, so it has to die with its parent
+        DrRunCommand {
+            program: "sh".to_owned(),
+            env: vec![],
+            dr_args: vec!["-c".to_owned(), "sleep 30; echo done".to_owned()],
+            tool_args: vec![],
+            target_args: vec![],
+            redirect: None,
+        }
+    }
+
+    fn collect_stop(handle: RunHandle) -> (Option<StopReason>, ExitStatus) {
+        let mut reason = None;
+        for event in handle.rx {
+            match event {
+                RunEvent::Stopped(r) => reason = Some(r),
+                RunEvent::Exited(s) => return (reason, s.unwrap()),
+                _ => {}
+            }
+        }
+        panic!("no exit event");
+    }
+
+    #[test]
+    fn timeout_kills_tree() {
+        let started = Instant::now();
+        let handle = start(&sleeper(), Some(Duration::from_millis(200)), || {}).unwrap();
+        let (reason, status) = collect_stop(handle);
+        assert_eq!(reason, Some(StopReason::Timeout));
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn stop_after_exit_kills_background() {
+        // the shell exits at once, the background sleep keeps its stdout open
+        let cmd = DrRunCommand {
+            dr_args: vec![
+                "-c".to_owned(),
+                "sleep 30 & echo started; exit 3".to_owned(),
+            ],
+            ..sleeper()
+        };
+        let started = Instant::now();
+        let handle = start(&cmd, Some(Duration::from_millis(500)), || {}).unwrap();
+        let (reason, status) = collect_stop(handle);
+        assert_eq!(reason, Some(StopReason::Timeout));
+        assert_eq!(status.code(), Some(3));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn stop_kills_tree() {
+        let started = Instant::now();
+        let handle = start(&sleeper(), None, || {}).unwrap();
+        handle.stop();
+        let (reason, status) = collect_stop(handle);
+        assert_eq!(reason, Some(StopReason::User));
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
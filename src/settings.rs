@@ -0,0 +1,502 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Version of the profiles file layout, bump it and extend `Profiles::from_json` on breaking changes.
+/// 0 is everything written before the version field: a flat settings.json or an early profiles.json.
+pub static SETTINGS_VERSION: u64 = 1;
+
+static PROFILES_FILE: &str = "profiles.json";
+// older versions kept a single settings.json, it's imported as the default profile
+static LEGACY_SETTINGS_FILE: &str = "settings.json";
+/// Overrides the profiles file location, `--config` takes precedence.
+pub static CONFIG_ENV: &str = "DR_SYMLOGGER_CONFIG";
+static DEFAULT_PROFILE: &str = "default";
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub enum DrToolInstrumentationMode {
+    Exec,
+    Inst,
+    #[default]
+    Invalid,
+}
+
+// missing keys get their values from Settings::default()
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub dr_dir: String,
+    pub dr_tool_path: String,
+    pub inst_module: String,
+    pub inst_mode: DrToolInstrumentationMode,
+    pub substr: String,
+    pub redirect_to_file: String, // 2>&1 > file.txt
+    pub cmd: String,
+    pub timeout_secs: u64,     // 0 means no timeout
+    pub catalogue_url: String, // DynamoRIO releases, empty for the built-in list
+    pub tools_dir: String,     // managed installs, empty for the per-user data dir
+    pub symbol_path: String,   // `_NT_SYMBOL_PATH` syntax, empty to use the environment variable
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            dr_dir: "".to_owned(),
+            dr_tool_path: "".to_owned(),
+            inst_module: "cmd.exe".to_owned(),
+            inst_mode: DrToolInstrumentationMode::Exec,
+            substr: "".to_owned(),
+            redirect_to_file: "log.txt".to_owned(),
+            cmd: "cmd.exe /c cmd.bat".to_owned(),
+            timeout_secs: 0,
+            catalogue_url: "".to_owned(),
+            tools_dir: "".to_owned(),
+            symbol_path: "".to_owned(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn timeout(&self) -> Option<std::time::Duration> {
+        if self.timeout_secs == 0 {
+            None
+        } else {
+            Some(std::time::Duration::from_secs(self.timeout_secs))
+        }
+    }
+}
+
+/// Named settings, `current` is the one shown in the GUI and used by the CLI by default.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+    pub version: u64,
+    pub current: String,
+    pub profiles: BTreeMap<String, Settings>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    is_read_only: bool, // the file is from a newer version, it's never overwritten
+}
+
+/// Why a profiles file isn't loaded.
+#[derive(Debug)]
+enum LoadError {
+    Invalid(String),
+    Newer(u64), // the version of the file, the file itself is fine
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) => f.write_str(e),
+            Self::Newer(version) => write!(
+                f,
+                "version {} is newer than supported {}",
+                version, SETTINGS_VERSION
+            ),
+        }
+    }
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self::with_default(Settings::default())
+    }
+}
+
+impl Profiles {
+    fn with_default(settings: Settings) -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            current: DEFAULT_PROFILE.to_owned(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_owned(), settings)]),
+            path: PathBuf::new(),
+            is_read_only: false,
+        }
+    }
+
+    /// Loads the profiles file at `path`, see `config_path`. A file that can't be loaded is moved
+    /// aside and the defaults are used, problems are reported to `log_text`. A file from a newer
+    /// version is kept as is, the defaults are used and never saved over it. Without a file the
+    /// files older versions kept in `legacy_dir` are imported.
+    pub fn load(path: &Path, legacy_dir: &Path, log_text: &mut String) -> Self {
+        let mut profiles = match Self::load_file(path, log_text) {
+            Ok(Some(profiles)) => profiles,
+            Ok(None) => Self::import(path, legacy_dir, log_text),
+            Err(LoadError::Newer(version)) => {
+                log_text.push_str(&format!(
+                    "Error: {:?} has newer version {}, the defaults are used and not saved\n",
+                    path, version
+                ));
+                Self {
+                    is_read_only: true,
+                    ..Self::default()
+                }
+            }
+            Err(LoadError::Invalid(e)) => {
+                recover(path, &e, log_text);
+                Self::default()
+            }
+        };
+        profiles.path = path.to_owned();
+        profiles
+    }
+
+    /// One-time import of the files older versions kept in the working directory, `legacy_dir`.
+    fn import(path: &Path, legacy_dir: &Path, log_text: &mut String) -> Self {
+        for old in [PROFILES_FILE, LEGACY_SETTINGS_FILE].map(|f| legacy_dir.join(f)) {
+            match Self::load_file(&old, log_text) {
+                Ok(Some(mut profiles)) => {
+                    profiles.path = path.to_owned();
+                    match profiles.save() {
+                        Ok(()) => log_text.push_str(&format!(
+                            "Settings imported from {:?} to {:?}, the old file isn't used anymore\n",
+                            old, path
+                        )),
+                        Err(e) => log_text.push_str(&format!("Error: {}\n", e)),
+                    }
+                    return profiles;
+                }
+                Ok(None) => {}
+                Err(e @ LoadError::Newer(_)) => log_text.push_str(&format!(
+                    "Error: can't import settings from {:?}: {}\n",
+                    old, e
+                )),
+                Err(LoadError::Invalid(e)) => recover(&old, &e, log_text),
+            }
+        }
+        Self::default()
+    }
+
+    /// `None` if there is no such file.
+    fn load_file(path: &Path, log_text: &mut String) -> Result<Option<Self>, LoadError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(path).map_err(|e| LoadError::Invalid(e.to_string()))?;
+        let (profiles, version) = Self::from_json(&text)?;
+        if version < SETTINGS_VERSION {
+            log_text.push_str(&format!(
+                "Settings in {:?} migrated from version {} to {}\n",
+                path, version, SETTINGS_VERSION
+            ));
+        }
+        Ok(Some(profiles))
+    }
+
+    /// Parses any known layout and migrates it to the current one, also returns the original version.
+    fn from_json(text: &str) -> Result<(Self, u64), LoadError> {
+        let invalid = |e: serde_json::Error| LoadError::Invalid(e.to_string());
+        let mut value: Value = serde_json::from_str(text).map_err(invalid)?;
+        let version = match value.get("version") {
+            None => 0,
+            Some(v) => v
+                .as_u64()
+                .ok_or_else(|| LoadError::Invalid(format!("invalid version {}", v)))?,
+        };
+        if version > SETTINGS_VERSION {
+            return Err(LoadError::Newer(version));
+        }
+        if version == 0 && value.get("profiles").is_none() {
+            // flat settings.json becomes the default profile
+            value = json!({ "current": DEFAULT_PROFILE, "profiles": { DEFAULT_PROFILE: value } });
+        }
+        let mut profiles: Self = serde_json::from_value(value).map_err(invalid)?;
+        profiles.version = SETTINGS_VERSION;
+        profiles.fix_current();
+        Ok((profiles, version))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if self.is_read_only {
+            return Err(format!(
+                "{:?} is from a newer version, the settings aren't saved over it",
+                self.path
+            ));
+        }
+        let profiles_str = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("can't create config dir {:?}: {}", dir, e))?;
+        }
+        std::fs::write(&self.path, profiles_str)
+            .map_err(|e| format!("can't save settings to {:?}: {}", self.path, e))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keeps `current` pointing to an existing profile, there is always at least one.
+    fn fix_current(&mut self) {
+        if self.profiles.contains_key(&self.current) {
+            return;
+        }
+        match self.profiles.keys().next() {
+            Some(name) => self.current = name.clone(),
+            None => *self = Self::default(),
+        }
+    }
+
+    pub fn current(&self) -> &Settings {
+        &self.profiles[&self.current]
+    }
+
+    /// Stores edited settings into the current profile.
+    pub fn set_current(&mut self, settings: Settings) {
+        self.profiles.insert(self.current.clone(), settings);
+    }
+
+    pub fn select(&mut self, name: &str) -> Result<(), String> {
+        if !self.profiles.contains_key(name) {
+            return Err(format!("no profile named {:?}", name));
+        }
+        self.current = name.to_owned();
+        Ok(())
+    }
+
+    /// Adds a new profile and selects it.
+    pub fn create(&mut self, name: &str, settings: Settings) -> Result<(), String> {
+        self.check_new_name(name)?;
+        self.profiles.insert(name.to_owned(), settings);
+        self.current = name.to_owned();
+        Ok(())
+    }
+
+    pub fn rename_current(&mut self, name: &str) -> Result<(), String> {
+        self.check_new_name(name)?;
+        let settings = self.profiles.remove(&self.current).unwrap();
+        self.profiles.insert(name.to_owned(), settings);
+        self.current = name.to_owned();
+        Ok(())
+    }
+
+    /// Removes the current profile and selects the first remaining one.
+    pub fn delete_current(&mut self) -> Result<(), String> {
+        if self.profiles.len() == 1 {
+            return Err("can't delete the last profile".to_owned());
+        }
+        self.profiles.remove(&self.current);
+        self.fix_current();
+        Ok(())
+    }
+
+    fn check_new_name(&self, name: &str) -> Result<(), String> {
+        if name.trim().is_empty() {
+            return Err("profile name can't be empty".to_owned());
+        }
+        if self.profiles.contains_key(name) {
+            return Err(format!("profile {:?} already exists", name));
+        }
+        Ok(())
+    }
+}
+
+/// The profiles file: `--config` value, then `CONFIG_ENV`, then the per-user config dir
+/// (`$XDG_CONFIG_HOME` or `~/.config` on Linux, `%APPDATA%` on Windows).
+pub fn config_path(arg: Option<&str>) -> PathBuf {
+    if let Some(path) = arg {
+        return PathBuf::from(path);
+    }
+    if let Some(path) = std::env::var_os(CONFIG_ENV).filter(|p| !p.is_empty()) {
+        return PathBuf::from(path);
+    }
+    match dirs::config_dir() {
+        Some(dir) => dir.join("dr_symlogger_launcher").join(PROFILES_FILE),
+        None => PathBuf::from(PROFILES_FILE),
+    }
+}
+
+fn recover(path: &Path, error: &str, log_text: &mut String) {
+    log_text.push_str(&format!(
+        "Error: can't load settings from {:?}: {}\n",
+        path, error
+    ));
+    match back_up(path) {
+        Ok(backup) => log_text.push_str(&format!(
+            "The file is moved to {:?}, its settings aren't used\n",
+            backup
+        )),
+        Err(e) => log_text.push_str(&format!(
+            "Error: can't back up {:?}: {}, it will be overwritten\n",
+            path, e
+        )),
+    }
+}
+
+/// Renames a broken settings file so it isn't overwritten, returns the new name.
+fn back_up(path: &Path) -> Result<PathBuf, String> {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}.bak", secs));
+    std::fs::rename(path, &backup).map_err(|e| e.to_string())?;
+    Ok(PathBuf::from(backup))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_and_duplicate() {
+        let mut profiles = Profiles::default();
+        profiles.create("parser", Settings::default()).unwrap();
+        assert_eq!(profiles.current, "parser");
+        let mut settings = profiles.current().clone();
+        settings.inst_module = "parser.dll".to_owned();
+        profiles.set_current(settings);
+        let copy = profiles.current().clone();
+        profiles.create("parser 2", copy).unwrap();
+        assert_eq!(profiles.current().inst_module, "parser.dll");
+        assert_eq!(profiles.profiles.len(), 3);
+        assert!(profiles.create("parser", Settings::default()).is_err());
+        assert!(profiles.create(" ", Settings::default()).is_err());
+    }
+
+    #[test]
+    fn rename_and_delete() {
+        let mut profiles = Profiles::default();
+        assert!(profiles.delete_current().is_err());
+        profiles.create("harness", Settings::default()).unwrap();
+        profiles.rename_current("tests").unwrap();
+        assert_eq!(profiles.current, "tests");
+        assert!(!profiles.profiles.contains_key("harness"));
+        assert!(profiles.rename_current(DEFAULT_PROFILE).is_err());
+        profiles.delete_current().unwrap();
+        assert_eq!(profiles.current, DEFAULT_PROFILE);
+        assert!(profiles.select("tests").is_err());
+    }
+
+    #[test]
+    fn missing_current_is_fixed() {
+        let mut profiles: Profiles =
+            serde_json::from_str(r#"{"current": "gone", "profiles": {}}"#).unwrap();
+        profiles.fix_current();
+        assert_eq!(profiles.current, DEFAULT_PROFILE);
+        assert_eq!(profiles.current().inst_module, "cmd.exe");
+    }
+
+    #[test]
+    fn migrate_flat_settings() {
+        let (profiles, version) = Profiles::from_json(
+            r#"{"dr_dir": "C:\\dr", "dr_tool_path": "", "inst_module": "app.exe",
+                "inst_mode": "Inst", "substr": "", "redirect_to_file": "", "cmd": "app.exe"}"#,
+        )
+        .unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(profiles.version, SETTINGS_VERSION);
+        assert_eq!(profiles.current, DEFAULT_PROFILE);
+        assert_eq!(profiles.current().dr_dir, r"C:\dr");
+        assert_eq!(
+            profiles.current().inst_mode,
+            DrToolInstrumentationMode::Inst
+        );
+        assert_eq!(profiles.current().timeout_secs, 0);
+    }
+
+    #[test]
+    fn missing_keys_get_defaults() {
+        let (profiles, version) = Profiles::from_json(
+            r#"{"version": 1, "profiles": {"parser": {"inst_module": "parser.dll"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(profiles.current, "parser");
+        let settings = profiles.current();
+        assert_eq!(settings.inst_module, "parser.dll");
+        assert_eq!(settings.inst_mode, DrToolInstrumentationMode::Exec);
+        assert_eq!(settings.redirect_to_file, "log.txt");
+    }
+
+    #[test]
+    fn bad_files_are_errors() {
+        assert!(Profiles::from_json(r#"{"dr_dir": "#).is_err());
+        assert!(Profiles::from_json(r#"{"inst_mode": "Bogus"}"#).is_err());
+        assert!(Profiles::from_json(r#"{"version": "1"}"#).is_err());
+        let newer = format!(r#"{{"version": {}}}"#, SETTINGS_VERSION + 1);
+        assert!(matches!(
+            Profiles::from_json(&newer),
+            Err(LoadError::Newer(v)) if v == SETTINGS_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn newer_file_is_kept() {
+        let dir = std::env::temp_dir().join(format!("dr_symlogger_newer_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(PROFILES_FILE);
+        let newer = format!(r#"{{"version": {}}}"#, SETTINGS_VERSION + 1);
+        std::fs::write(&path, &newer).unwrap();
+        let mut log_text = String::new();
+        let profiles = Profiles::load(&path, &dir, &mut log_text);
+        let saved = profiles.save();
+        let text = std::fs::read_to_string(&path);
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(log_text.contains("newer version"));
+        assert_eq!(profiles.current, DEFAULT_PROFILE);
+        assert!(saved.is_err());
+        assert_eq!(text.unwrap(), newer);
+        assert_eq!(files, 1); // no backup
+    }
+
+    #[test]
+    fn save_creates_config_dir() {
+        let dir = std::env::temp_dir().join(format!("dr_symlogger_config_{}", std::process::id()));
+        let path = dir.join("nested").join(PROFILES_FILE);
+        let mut log_text = String::new();
+        // an empty legacy dir, nothing to import
+        let mut profiles = Profiles::load(&path, &dir, &mut log_text);
+        profiles.create("parser", Settings::default()).unwrap();
+        profiles.save().unwrap();
+        let profiles = Profiles::load(&path, &dir, &mut log_text);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(profiles.current, "parser");
+    }
+
+    #[test]
+    fn config_path_precedence() {
+        // the only test touching the variable
+        let saved = std::env::var_os(CONFIG_ENV);
+        std::env::set_var(CONFIG_ENV, "env.json");
+        let with_arg = config_path(Some("x.json"));
+        let with_env = config_path(None);
+        std::env::set_var(CONFIG_ENV, "");
+        let with_empty_env = config_path(None);
+        std::env::remove_var(CONFIG_ENV);
+        let default = config_path(None);
+        if let Some(saved) = saved {
+            std::env::set_var(CONFIG_ENV, saved);
+        }
+
+        assert_eq!(with_arg, PathBuf::from("x.json"));
+        assert_eq!(with_env, PathBuf::from("env.json"));
+        assert!(default.ends_with(PROFILES_FILE));
+        if let Some(dir) = dirs::config_dir() {
+            assert!(default.starts_with(dir));
+        }
+        assert_eq!(with_empty_env, default);
+    }
+
+    #[test]
+    fn import_from_legacy_dir() {
+        let dir = std::env::temp_dir().join(format!("dr_symlogger_legacy_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let legacy = dir.join(LEGACY_SETTINGS_FILE);
+        std::fs::write(&legacy, r#"{"inst_module": "app.exe"}"#).unwrap();
+        let path = dir.join("config").join(PROFILES_FILE);
+        let mut log_text = String::new();
+        let profiles = Profiles::load(&path, &dir, &mut log_text);
+        let is_saved = path.exists();
+        let is_legacy_kept = legacy.exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(profiles.current().inst_module, "app.exe");
+        assert!(is_saved);
+        assert!(is_legacy_kept);
+        assert!(log_text.contains("imported"));
+    }
+}
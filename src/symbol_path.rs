@@ -0,0 +1,323 @@
+// `_NT_SYMBOL_PATH` syntax: `;`-separated elements, each one is
+//   <dir>                                   a plain directory
+//   cache*[<dir>]                           cache for the elements after it, default cache if empty
+//   srv*[<store>*]...<server>               symbol server, same as symsrv*symsrv.dll*...
+//   symsrv*<dll>*[<store>*]...<server>      symbol server through another symsrv dll
+// Stores before the server are downstream ones, files from the server are copied into them.
+// An empty store stands for the default one.
+
+use std::fmt;
+use std::path::Path;
+
+pub static SYMBOL_PATH_ENV: &str = "_NT_SYMBOL_PATH";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolPathElement {
+    Dir(String),
+    Cache(Option<String>),
+    Server {
+        dll: String,
+        stores: Vec<String>, // downstream stores, nearest first, empty for the default one
+        server: String,      // url or share
+    },
+}
+
+pub static DEFAULT_SYMSRV_DLL: &str = "symsrv.dll";
+
+impl SymbolPathElement {
+    fn parse(text: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = text.split('*').map(str::trim).collect();
+        let keyword = parts[0].to_lowercase();
+        let server = |dll: &str, rest: &[&str]| match rest.split_last() {
+            Some((server, stores)) if !server.is_empty() => Ok(Self::Server {
+                dll: dll.to_owned(),
+                stores: stores.iter().map(|s| s.to_string()).collect(),
+                server: server.to_string(),
+            }),
+            _ => Err(format!("{:?} has no symbol server", text)),
+        };
+        match keyword.as_str() {
+            "srv" if parts.len() > 1 => server(DEFAULT_SYMSRV_DLL, &parts[1..]),
+            "symsrv" if parts.len() > 2 && !parts[1].is_empty() => server(parts[1], &parts[2..]),
+            "symsrv" => Err(format!("{:?} has no symsrv dll", text)),
+            "cache" if parts.len() == 2 => Ok(Self::Cache(
+                Some(parts[1].to_owned()).filter(|dir| !dir.is_empty()),
+            )),
+            "cache" => Err(format!("{:?} should be cache*<dir>", text)),
+            _ if parts.len() == 1 => Ok(Self::Dir(parts[0].to_owned())),
+            _ => Err(format!("unknown symbol path element {:?}", text)),
+        }
+    }
+}
+
+impl fmt::Display for SymbolPathElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Dir(dir) => write!(f, "{}", dir),
+            Self::Cache(dir) => write!(f, "cache*{}", dir.as_deref().unwrap_or_default()),
+            Self::Server {
+                dll,
+                stores,
+                server,
+            } => {
+                if dll == DEFAULT_SYMSRV_DLL {
+                    write!(f, "srv*")?;
+                } else {
+                    write!(f, "symsrv*{}*", dll)?;
+                }
+                for store in stores {
+                    write!(f, "{}*", store)?;
+                }
+                write!(f, "{}", server)
+            }
+        }
+    }
+}
+
+/// Directories and shares, not urls.
+pub fn is_local(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    !path.is_empty() && !lower.starts_with("http://") && !lower.starts_with("https://")
+}
+
+/// Parses the whole symbol path, empty elements are skipped.
+pub fn parse(text: &str) -> Result<Vec<SymbolPathElement>, String> {
+    text.split(';')
+        .map(str::trim)
+        .filter(|element| !element.is_empty())
+        .map(SymbolPathElement::parse)
+        .collect()
+}
+
+pub fn format(elements: &[SymbolPathElement]) -> String {
+    let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+    elements.join(";")
+}
+
+/// Directories and shares that can be searched without a symbol server, in search order.
+pub fn local_stores(elements: &[SymbolPathElement]) -> Vec<String> {
+    let mut stores: Vec<String> = Vec::new();
+    let mut add = |path: &str| {
+        if is_local(path) && !stores.iter().any(|s| s == path) {
+            stores.push(path.to_owned());
+        }
+    };
+    for element in elements {
+        match element {
+            SymbolPathElement::Dir(dir) => add(dir),
+            SymbolPathElement::Cache(dir) => add(dir.as_deref().unwrap_or_default()),
+            SymbolPathElement::Server { stores, server, .. } => {
+                for store in stores {
+                    add(store);
+                }
+                add(server);
+            }
+        }
+    }
+    stores
+}
+
+/// Splits the stores joined by `check_symbol_path()`.
+pub fn split_stores(stores: &str) -> Vec<String> {
+    stores
+        .split(';')
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Local stores that don't exist.
+pub fn missing_dirs(elements: &[SymbolPathElement]) -> Vec<String> {
+    local_stores(elements)
+        .into_iter()
+        .filter(|dir| !Path::new(dir).is_dir())
+        .collect()
+}
+
+/// Returns the local stores of the profile's symbol path, or of `_NT_SYMBOL_PATH` if it's empty,
+/// joined for DR. Problems are reported into `log_text`.
+pub fn check_symbol_path(setting: &str, log_text: &mut String) -> String {
+    let (source, symbol_path) = if setting.is_empty() {
+        (
+            SYMBOL_PATH_ENV,
+            std::env::var(SYMBOL_PATH_ENV).unwrap_or_default(),
+        )
+    } else {
+        ("Symbol path", setting.to_owned())
+    };
+    if symbol_path.is_empty() {
+        log_text.push_str(
+            format!(
+                "Symbol path is empty, set it to something like \"{}\"\n",
+                "srv*c:\\symbols*https://msdl.microsoft.com/download/symbols"
+            )
+            .as_str(),
+        );
+        return "".to_owned();
+    }
+    let elements = match parse(&symbol_path) {
+        Ok(elements) => elements,
+        Err(e) => {
+            log_text.push_str(format!("Error: invalid {}: {}\n", source, e).as_str());
+            return "".to_owned();
+        }
+    };
+    log_text.push_str(&format!("{} is ok: {:?}\n", source, symbol_path));
+    for dir in missing_dirs(&elements) {
+        log_text.push_str(format!("Warning: symbol store {:?} doesn't exist\n", dir).as_str());
+    }
+    let servers: Vec<&str> = elements
+        .iter()
+        .filter_map(|element| match element {
+            SymbolPathElement::Server { server, .. } if !is_local(server) => Some(server.as_str()),
+            _ => None,
+        })
+        .collect();
+    if !servers.is_empty() {
+        log_text.push_str(
+            format!(
+                "Symbol servers aren't queried by DR, only their local stores: {}\n",
+                servers.join(", ")
+            )
+            .as_str(),
+        );
+    }
+    let stores = local_stores(&elements).join(";");
+    if stores.is_empty() {
+        log_text.push_str(format!("Warning: no local paths in {} found\n", source).as_str());
+    } else {
+        log_text.push_str(format!("Symbol path for DR: {}\n", stores).as_str());
+    }
+    stores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(dll: &str, stores: &[&str], server: &str) -> SymbolPathElement {
+        SymbolPathElement::Server {
+            dll: dll.to_owned(),
+            stores: stores.iter().map(|s| s.to_string()).collect(),
+            server: server.to_owned(),
+        }
+    }
+
+    #[test]
+    fn plain_dirs() {
+        let elements = parse("c:\\symbols; ;d:\\build\\pdb;").unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                SymbolPathElement::Dir("c:\\symbols".to_owned()),
+                SymbolPathElement::Dir("d:\\build\\pdb".to_owned()),
+            ]
+        );
+        assert_eq!(
+            local_stores(&elements),
+            vec!["c:\\symbols", "d:\\build\\pdb"]
+        );
+    }
+
+    #[test]
+    fn srv() {
+        let url = "https://msdl.microsoft.com/download/symbols";
+        assert_eq!(
+            parse(&format!("srv*c:\\symbols*{}", url)).unwrap(),
+            vec![server("symsrv.dll", &["c:\\symbols"], url)]
+        );
+        assert_eq!(
+            parse(&format!("SRV*{}", url)).unwrap(),
+            vec![server("symsrv.dll", &[], url)]
+        );
+        // default downstream store, then a share
+        let elements = parse(&format!("srv**\\\\share\\symbols*{}", url)).unwrap();
+        assert_eq!(
+            elements,
+            vec![server("symsrv.dll", &["", "\\\\share\\symbols"], url)]
+        );
+        assert_eq!(local_stores(&elements), vec!["\\\\share\\symbols"]);
+        // a share can be the server too
+        let elements = parse("srv*c:\\cache*\\\\share\\symbols").unwrap();
+        assert_eq!(
+            local_stores(&elements),
+            vec!["c:\\cache", "\\\\share\\symbols"]
+        );
+        assert!(parse("srv*").is_err());
+        assert!(parse("srv*c:\\symbols*").is_err());
+    }
+
+    #[test]
+    fn symsrv() {
+        let url = "https://symbols.mozilla.org";
+        assert_eq!(
+            parse(&format!("symsrv*symsrv.dll*c:\\symbols*{}", url)).unwrap(),
+            vec![server("symsrv.dll", &["c:\\symbols"], url)]
+        );
+        assert_eq!(
+            parse(&format!("symsrv*mysrv.dll*{}", url)).unwrap(),
+            vec![server("mysrv.dll", &[], url)]
+        );
+        assert!(parse("symsrv*symsrv.dll").is_err());
+        assert!(parse(&format!("symsrv**{}", url)).is_err());
+    }
+
+    #[test]
+    fn cache() {
+        let elements = parse("cache*c:\\cache;cache*;srv*https://example.com").unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                SymbolPathElement::Cache(Some("c:\\cache".to_owned())),
+                SymbolPathElement::Cache(None),
+                server("symsrv.dll", &[], "https://example.com"),
+            ]
+        );
+        assert_eq!(local_stores(&elements), vec!["c:\\cache"]);
+        assert!(parse("cache*a*b").is_err());
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let text = "d:\\pdb;cache*;cache*c:\\cache;srv**c:\\symbols*https://example.com;\
+                    symsrv*mysrv.dll*\\\\share";
+        let elements = parse(text).unwrap();
+        assert_eq!(format(&elements), text);
+        assert_eq!(parse(&format(&elements)).unwrap(), elements);
+    }
+
+    #[test]
+    fn missing_stores() {
+        let dir = std::env::temp_dir();
+        let missing = dir.join(format!("no_symbols_{}", std::process::id()));
+        let text = format!(
+            "{};srv*{}*https://example.com",
+            dir.display(),
+            missing.display()
+        );
+        let elements = parse(&text).unwrap();
+        assert_eq!(missing_dirs(&elements), vec![missing.display().to_string()]);
+        let mut log_text = String::new();
+        assert_eq!(
+            check_symbol_path(&text, &mut log_text),
+            format!("{};{}", dir.display(), missing.display())
+        );
+        assert!(log_text.contains("doesn't exist"));
+    }
+
+    #[test]
+    fn mixed() {
+        let elements = parse(
+            "d:\\pdb;cache*c:\\cache;srv*c:\\symbols*https://msdl.microsoft.com/download/symbols;\
+             c:\\symbols",
+        )
+        .unwrap();
+        assert_eq!(elements.len(), 4);
+        // duplicates are dropped, the order is kept
+        assert_eq!(
+            local_stores(&elements),
+            vec!["d:\\pdb", "c:\\cache", "c:\\symbols"]
+        );
+        assert!(parse("d:\\pdb;foo*bar").is_err());
+    }
+}
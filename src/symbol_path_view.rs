@@ -0,0 +1,338 @@
+// Window editing the profile's symbol path element by element.
+
+use crate::symbol_path::{self, is_local, SymbolPathElement, DEFAULT_SYMSRV_DLL, SYMBOL_PATH_ENV};
+use eframe::egui;
+use std::path::Path;
+
+static DEFAULT_SERVER: &str = "https://msdl.microsoft.com/download/symbols";
+
+enum RowAction {
+    Up(usize),
+    Down(usize),
+    Remove(usize),
+}
+
+#[derive(Default)]
+pub struct SymbolPathView {
+    pub open: bool,
+    text: String, // what `elements` were parsed from
+    elements: Vec<SymbolPathElement>,
+    error: Option<String>,
+    is_dirty: bool, // `elements` were changed in the window and aren't written back yet
+}
+
+impl SymbolPathView {
+    /// Edits `symbol_path` in place, a value edited elsewhere is parsed again. The value is only
+    /// rewritten after an edit in the window, so it's kept as typed otherwise.
+    pub fn show(&mut self, ctx: &egui::Context, symbol_path: &mut String) {
+        self.reparse(symbol_path);
+        // left over from an earlier frame, the edit can't be formatted yet
+        let is_pending = self.is_dirty;
+        let mut open = self.open;
+        egui::Window::new("Symbol path")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if let Some(e) = &self.error {
+                    ui.colored_label(egui::Color32::RED, format!("Invalid symbol path: {}", e));
+                    return;
+                }
+                if self.elements.is_empty() {
+                    let env = std::env::var(SYMBOL_PATH_ENV).unwrap_or_default();
+                    ui.label(format!("Empty, {} is used: {:?}", SYMBOL_PATH_ENV, env));
+                    if !env.is_empty()
+                        && ui
+                            .button(format!("Copy {}", SYMBOL_PATH_ENV))
+                            .on_hover_text("Edit the environment variable's value in this profile")
+                            .clicked()
+                    {
+                        if let Ok(elements) = symbol_path::parse(&env) {
+                            self.elements = elements;
+                            self.is_dirty = true;
+                        }
+                    }
+                }
+                let action = self.show_elements(ui);
+                match action {
+                    Some(RowAction::Up(i)) if i > 0 => self.elements.swap(i - 1, i),
+                    Some(RowAction::Down(i)) if i + 1 < self.elements.len() => {
+                        self.elements.swap(i, i + 1)
+                    }
+                    Some(RowAction::Remove(i)) => {
+                        self.elements.remove(i);
+                    }
+                    _ => {}
+                }
+                self.is_dirty |= action.is_some();
+                if is_pending {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        "☹ Changes aren't applied until the marked fields are fixed",
+                    );
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("➕ Directory")
+                        .on_hover_text("A directory with PDB files or a symbol store")
+                        .clicked()
+                    {
+                        self.elements.push(SymbolPathElement::Dir("".to_owned()));
+                        self.is_dirty = true;
+                    }
+                    if ui
+                        .button("➕ Server")
+                        .on_hover_text("A symbol server with the local stores it's cached in")
+                        .clicked()
+                    {
+                        self.elements.push(SymbolPathElement::Server {
+                            dll: DEFAULT_SYMSRV_DLL.to_owned(),
+                            stores: Vec::new(),
+                            server: DEFAULT_SERVER.to_owned(),
+                        });
+                        self.is_dirty = true;
+                    }
+                    if ui
+                        .button("➕ Cache")
+                        .on_hover_text("Cache for the symbols found by the elements after it")
+                        .clicked()
+                    {
+                        self.elements.push(SymbolPathElement::Cache(None));
+                        self.is_dirty = true;
+                    }
+                });
+            });
+        self.open = open;
+        self.write_back(symbol_path);
+    }
+
+    /// Parses `symbol_path` again if it was edited elsewhere.
+    fn reparse(&mut self, symbol_path: &str) {
+        if symbol_path == self.text {
+            return;
+        }
+        self.text = symbol_path.to_owned();
+        self.is_dirty = false;
+        match symbol_path::parse(symbol_path) {
+            Ok(elements) => {
+                self.elements = elements;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Formats the elements into `symbol_path` after they were edited in the window. Elements that
+    /// wouldn't parse back the same, e.g. with a `;` in a dir, stay pending until they're fixed.
+    fn write_back(&mut self, symbol_path: &mut String) {
+        if !self.is_dirty || self.error.is_some() {
+            return;
+        }
+        let text = symbol_path::format(&self.elements);
+        if symbol_path::parse(&text).as_ref() != Ok(&self.elements) {
+            return;
+        }
+        self.is_dirty = false;
+        self.text = text;
+        *symbol_path = self.text.clone();
+    }
+
+    /// Edits the rows in place, sets `is_dirty` on an edit and returns the clicked row button.
+    fn show_elements(&mut self, ui: &mut egui::Ui) -> Option<RowAction> {
+        let mut action = None;
+        let mut is_edited = false;
+        let count = self.elements.len();
+        egui::Grid::new("symbol_path_grid")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for (i, element) in self.elements.iter_mut().enumerate() {
+                    match element {
+                        SymbolPathElement::Dir(dir) => {
+                            ui.label("Directory");
+                            ui.horizontal(|ui| is_edited |= show_dir(ui, dir, ""));
+                        }
+                        SymbolPathElement::Cache(dir) => {
+                            ui.label("Cache");
+                            ui.horizontal(|ui| {
+                                let mut text = dir.clone().unwrap_or_default();
+                                if show_dir(ui, &mut text, "default cache") {
+                                    *dir = Some(text).filter(|dir| !dir.is_empty());
+                                    is_edited = true;
+                                }
+                            });
+                        }
+                        SymbolPathElement::Server {
+                            dll,
+                            stores,
+                            server,
+                        } => {
+                            ui.label("Server").on_hover_text(dll.as_str());
+                            ui.horizontal(|ui| {
+                                // stores are edited as one field, split again the same way
+                                let mut text = stores.join("*");
+                                let response = ui
+                                    .add(
+                                        egui::TextEdit::singleline(&mut text)
+                                            .hint_text("local store")
+                                            .desired_width(150.0),
+                                    )
+                                    .on_hover_text(
+                                        "Local stores the server's files are copied into, \
+                                         separated by *, an empty one is the default store",
+                                    );
+                                if response.changed() {
+                                    *stores = if text.is_empty() {
+                                        Vec::new()
+                                    } else {
+                                        text.split('*').map(str::to_owned).collect()
+                                    };
+                                    is_edited = true;
+                                }
+                                is_edited |= ui
+                                    .add(
+                                        egui::TextEdit::singleline(server)
+                                            .hint_text(DEFAULT_SERVER),
+                                    )
+                                    .changed();
+                                let missing: Vec<&String> = stores
+                                    .iter()
+                                    .filter(|store| is_missing_dir(store))
+                                    .collect();
+                                let store_error =
+                                    stores.iter().find_map(|store| text_error(store, &[';']));
+                                if let Some(e) = store_error {
+                                    ui.colored_label(egui::Color32::RED, "☹")
+                                        .on_hover_text(format!("Store: {}", e));
+                                } else if !missing.is_empty() {
+                                    ui.colored_label(egui::Color32::RED, "☹").on_hover_text(
+                                        format!("Directory doesn't exist: {:?}", missing),
+                                    );
+                                }
+                                if server.is_empty() {
+                                    ui.colored_label(egui::Color32::RED, "☹")
+                                        .on_hover_text("Server can't be empty");
+                                } else if let Some(e) = text_error(server, &[';', '*']) {
+                                    ui.colored_label(egui::Color32::RED, "☹")
+                                        .on_hover_text(format!("Server: {}", e));
+                                }
+                            });
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(i > 0, egui::Button::new("⏶")).clicked() {
+                            action = Some(RowAction::Up(i));
+                        }
+                        if ui
+                            .add_enabled(i + 1 < count, egui::Button::new("⏷"))
+                            .clicked()
+                        {
+                            action = Some(RowAction::Down(i));
+                        }
+                        if ui.button("🗑").on_hover_text("Remove").clicked() {
+                            action = Some(RowAction::Remove(i));
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        self.is_dirty |= is_edited;
+        action
+    }
+}
+
+fn is_missing_dir(path: &str) -> bool {
+    is_local(path) && !Path::new(path).is_dir()
+}
+
+/// Why `text` can't be written into the symbol path as is, `separators` are the characters
+/// splitting it there.
+fn text_error(text: &str, separators: &[char]) -> Option<String> {
+    if let Some(c) = text.chars().find(|c| separators.contains(c)) {
+        Some(format!("Can't contain {:?}, it's a separator", c))
+    } else if text.trim() != text {
+        Some("Can't start or end with spaces".to_owned())
+    } else {
+        None
+    }
+}
+
+/// Returns whether `dir` was edited or picked.
+fn show_dir(ui: &mut egui::Ui, dir: &mut String, hint: &str) -> bool {
+    let mut is_edited = ui
+        .add(egui::TextEdit::singleline(dir).hint_text(hint))
+        .changed();
+    if ui
+        .button("📁🔍")
+        .on_hover_text("Open directory dialog")
+        .clicked()
+    {
+        if let Some(result) = rfd::FileDialog::new().pick_folder() {
+            *dir = result.display().to_string();
+            is_edited = true;
+        }
+    }
+    if let Some(e) = text_error(dir, &[';', '*']) {
+        ui.colored_label(egui::Color32::RED, "☹").on_hover_text(e);
+    } else if is_missing_dir(dir) {
+        ui.colored_label(egui::Color32::RED, "☹")
+            .on_hover_text("Directory doesn't exist");
+    } else if dir.is_empty() && hint.is_empty() {
+        ui.colored_label(egui::Color32::RED, "☹")
+            .on_hover_text("Directory can't be empty");
+    }
+    is_edited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `show` does around the window.
+    fn sync(view: &mut SymbolPathView, symbol_path: &mut String) {
+        view.reparse(symbol_path);
+        view.write_back(symbol_path);
+    }
+
+    #[test]
+    fn typed_text_is_kept() {
+        let mut view = SymbolPathView::default();
+        for typed in ["C:\\symbols;", "C:\\symbols ", "C:\\a;;C:\\b", ""] {
+            let mut symbol_path = typed.to_owned();
+            sync(&mut view, &mut symbol_path);
+            sync(&mut view, &mut symbol_path);
+            assert_eq!(symbol_path, typed);
+        }
+    }
+
+    #[test]
+    fn window_edits_are_formatted() {
+        let mut view = SymbolPathView::default();
+        let mut symbol_path = "C:\\a;".to_owned();
+        sync(&mut view, &mut symbol_path);
+        view.elements.push(SymbolPathElement::Cache(None));
+        view.is_dirty = true;
+        sync(&mut view, &mut symbol_path);
+        assert_eq!(symbol_path, symbol_path::format(&view.elements));
+        assert!(!view.is_dirty);
+        // a separator in a dir isn't written back until it's fixed
+        let mut symbol_path = "C:\\a".to_owned();
+        sync(&mut view, &mut symbol_path);
+        view.elements[0] = SymbolPathElement::Dir("C:\\a;b".to_owned());
+        view.is_dirty = true;
+        sync(&mut view, &mut symbol_path);
+        assert_eq!(symbol_path, "C:\\a");
+        assert!(view.is_dirty);
+        assert!(view.error.is_none());
+        view.elements[0] = SymbolPathElement::Dir("C:\\b".to_owned());
+        sync(&mut view, &mut symbol_path);
+        assert_eq!(symbol_path, "C:\\b");
+        assert!(text_error("C:\\a*b", &[';', '*']).is_some());
+        assert!(text_error(" C:\\a", &[';']).is_some());
+        assert!(text_error("C:\\a", &[';', '*']).is_none());
+        // an edit elsewhere replaces the window's pending one
+        view.is_dirty = true;
+        let mut symbol_path = "C:\\b;".to_owned();
+        sync(&mut view, &mut symbol_path);
+        assert_eq!(symbol_path, "C:\\b;");
+    }
+}
@@ -0,0 +1,140 @@
+// Searchable list of the instrumented module's functions, picks the grep substring.
+
+use crate::symbols::{matching, ModuleSymbols, Symbol};
+use eframe::egui;
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum SortBy {
+    Name,
+    #[default]
+    Rva,
+    Size,
+}
+
+#[derive(Default)]
+pub struct SymbolView {
+    pub open: bool,
+    symbols: Option<ModuleSymbols>, // functions sorted by `sort_by`
+    search: String,
+    sort_by: SortBy,
+    is_descending: bool,
+}
+
+impl SymbolView {
+    pub fn set(&mut self, symbols: ModuleSymbols) {
+        self.symbols = Some(symbols);
+        self.sort();
+        self.open = true;
+    }
+
+    /// Clicking a function makes its name the grep `substr`.
+    pub fn show(&mut self, ctx: &egui::Context, substr: &mut String) {
+        let Some(symbols) = &self.symbols else {
+            return;
+        };
+        let mut open = self.open;
+        let mut sort_by = None;
+        egui::Window::new("Symbols")
+            .open(&mut open)
+            .default_size(egui::vec2(600.0, 400.0))
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{}: {} functions from {}",
+                    symbols.module.display(),
+                    symbols.functions.len(),
+                    symbols.debug_file.display()
+                ));
+                if let Some(warning) = symbols.warning() {
+                    ui.colored_label(egui::Color32::RED, format!("☹ {}", warning));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Search");
+                    ui.text_edit_singleline(&mut self.search)
+                        .on_hover_text("Case sensitive, the same way the grep substring matches");
+                    if ui
+                        .add_enabled(!self.search.is_empty(), egui::Button::new("Use as grep"))
+                        .on_hover_text("Log only the functions matching the search")
+                        .clicked()
+                    {
+                        *substr = self.search.clone();
+                    }
+                });
+                ui.label(format!(
+                    "Grep {:?} matches {} functions",
+                    substr,
+                    matching(&symbols.functions, substr).count()
+                ));
+                ui.separator();
+
+                let shown: Vec<&Symbol> = matching(&symbols.functions, &self.search).collect();
+                ui.label(format!("{} shown", shown.len()));
+                egui::Grid::new("symbols_header")
+                    .num_columns(3)
+                    .min_col_width(100.0)
+                    .show(ui, |ui| {
+                        for (column, name) in [
+                            (SortBy::Rva, "RVA"),
+                            (SortBy::Size, "Size"),
+                            (SortBy::Name, "Name"),
+                        ] {
+                            let arrow = match (column == self.sort_by, self.is_descending) {
+                                (false, _) => "",
+                                (true, false) => " ⏶",
+                                (true, true) => " ⏷",
+                            };
+                            if ui.button(format!("{}{}", name, arrow)).clicked() {
+                                sort_by = Some(column);
+                            }
+                        }
+                        ui.end_row();
+                    });
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + 4.0;
+                egui::ScrollArea::vertical().show_rows(ui, row_height, shown.len(), |ui, rows| {
+                    egui::Grid::new("symbols_grid")
+                        .num_columns(3)
+                        .min_col_width(100.0)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for f in &shown[rows] {
+                                ui.monospace(format!("{:08x}", f.rva));
+                                if f.size == 0 {
+                                    ui.label("");
+                                } else {
+                                    ui.monospace(format!("{:x}", f.size));
+                                }
+                                if ui
+                                    .selectable_label(*substr == f.name, &f.name)
+                                    .on_hover_text("Use as the grep substring")
+                                    .clicked()
+                                {
+                                    *substr = f.name.clone();
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+        self.open = open;
+        if let Some(column) = sort_by {
+            // the same column again flips the order
+            self.is_descending = column == self.sort_by && !self.is_descending;
+            self.sort_by = column;
+            self.sort();
+        }
+    }
+
+    fn sort(&mut self) {
+        let Some(symbols) = &mut self.symbols else {
+            return;
+        };
+        let functions = &mut symbols.functions;
+        match self.sort_by {
+            SortBy::Name => functions.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortBy::Rva => functions.sort_by_key(|f| f.rva),
+            SortBy::Size => functions.sort_by_key(|f| f.size),
+        }
+        if self.is_descending {
+            functions.reverse();
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// Function symbols of the instrumented module, read by the launcher itself so it can tell before
+// a run whether DR will find any, on any host and without a debugger.
+
+use crate::elf;
+use crate::settings::Settings;
+use crate::symstore::{self, PdbId};
+use pdb::FallibleIterator;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub rva: u32,
+    pub size: u32, // 0 if unknown
+}
+
+#[derive(Debug)]
+pub struct ModuleSymbols {
+    pub module: PathBuf,
+    pub debug_file: PathBuf,    // where the symbols were read from
+    pub functions: Vec<Symbol>, // sorted by RVA
+    pub is_exports_only: bool,  // no debug symbols, only the exported functions
+}
+
+impl ModuleSymbols {
+    /// Why the functions may not be enough for a run.
+    pub fn warning(&self) -> Option<&'static str> {
+        if self.functions.is_empty() {
+            Some("no function symbols, nothing will be logged")
+        } else if self.is_exports_only {
+            Some("only the exported functions, install the debug symbols for the rest")
+        } else {
+            None
+        }
+    }
+}
+
+/// Functions DrSymLogger's `--printSymsGrep` would pick, the match is case sensitive.
+pub fn matching<'a>(functions: &'a [Symbol], substr: &'a str) -> impl Iterator<Item = &'a Symbol> {
+    functions.iter().filter(move |f| f.name.contains(substr))
+}
+
+/// Locates the module and reads the functions from its PDB in `stores`, or from the ELF module
+/// and its debug file.
+pub fn module_symbols(settings: &Settings, stores: &[String]) -> Result<ModuleSymbols, String> {
+    let module = symstore::locate_module(settings)?;
+    if elf::is_elf(&module) {
+        return elf::module_symbols(&module, &[PathBuf::from(elf::DEBUG_DIR)]);
+    }
+    let id = PdbId::of_module(&module)?;
+    let debug_file = symstore::find(stores, &id).ok_or_else(|| {
+        format!(
+            "{} {} of {} isn't in the symbol stores",
+            id.name,
+            id.key(),
+            module.display()
+        )
+    })?;
+    let functions = pdb_functions(&debug_file)?;
+    Ok(ModuleSymbols {
+        module,
+        debug_file,
+        functions,
+        // publics are all the functions of the image, not only the exported ones
+        is_exports_only: false,
+    })
+}
+
+/// Procedures of all the modules of the PDB, with the public functions that have none.
+pub fn pdb_functions(path: &Path) -> Result<Vec<Symbol>, String> {
+    let error = |e: pdb::Error| format!("can't read {}: {}", path.display(), e);
+    let file =
+        std::fs::File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut pdb = pdb::PDB::open(file).map_err(error)?;
+    let address_map = pdb.address_map().map_err(error)?;
+    let mut functions = BTreeMap::new();
+
+    // procedures have the undecorated names and the sizes
+    let dbi = pdb.debug_information().map_err(error)?;
+    let mut modules = dbi.modules().map_err(error)?;
+    while let Some(module) = modules.next().map_err(error)? {
+        let Some(info) = pdb.module_info(&module).map_err(error)? else {
+            continue;
+        };
+        let mut symbols = info.symbols().map_err(error)?;
+        while let Some(symbol) = symbols.next().map_err(error)? {
+            if let Ok(pdb::SymbolData::Procedure(proc)) = symbol.parse() {
+                if let Some(rva) = proc.offset.to_rva(&address_map) {
+                    functions.entry(rva.0).or_insert(Symbol {
+                        name: proc.name.to_string().into_owned(),
+                        rva: rva.0,
+                        size: proc.len,
+                    });
+                }
+            }
+        }
+    }
+
+    // stripped PDBs only have the publics
+    let globals = pdb.global_symbols().map_err(error)?;
+    let mut symbols = globals.iter();
+    while let Some(symbol) = symbols.next().map_err(error)? {
+        match symbol.parse() {
+            Ok(pdb::SymbolData::Public(public)) if public.function => {
+                if let Some(rva) = public.offset.to_rva(&address_map) {
+                    functions.entry(rva.0).or_insert(Symbol {
+                        name: public.name.to_string().into_owned(),
+                        rva: rva.0,
+                        size: 0,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(functions.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::{self, Function};
+
+    static GUID: [u8; 16] = [7; 16];
+
+    #[test]
+    fn functions_from_pdb() {
+        let dir = std::env::temp_dir().join(format!("symbols_pdb_{}", std::process::id()));
+        let store = dir.join("store");
+        std::fs::create_dir_all(&dir).unwrap();
+        let functions = [
+            Function {
+                name: "main",
+                rva: 0x1010,
+                len: 0x20,
+            },
+            Function {
+                name: "?main@@YAHXZ",
+                rva: 0x1010,
+                len: 0,
+            },
+            Function {
+                name: "?helper@@YAXXZ",
+                rva: 0x1100,
+                len: 0,
+            },
+            Function {
+                name: "parse",
+                rva: 0x1040,
+                len: 0x80,
+            },
+        ];
+        let pdb = dir.join("app.pdb");
+        std::fs::write(&pdb, test_files::pdb_with_functions(&GUID, 1, &functions)).unwrap();
+        std::fs::write(
+            dir.join("app.exe"),
+            test_files::pe(&GUID, 1, "C:\\src\\app.pdb"),
+        )
+        .unwrap();
+        symstore::import(&store, &pdb).unwrap();
+        let settings = Settings {
+            inst_module: dir.join("app.exe").display().to_string(),
+            ..Settings::default()
+        };
+
+        let found = module_symbols(&settings, &[store.display().to_string()]);
+        let missing = module_symbols(&settings, &[dir.join("none").display().to_string()]);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let found = found.unwrap();
+        assert!(found.debug_file.starts_with(&store));
+        let symbol = |name: &str, rva, size| Symbol {
+            name: name.to_owned(),
+            rva,
+            size,
+        };
+        // the procedure wins over the public at the same address
+        assert_eq!(
+            found.functions,
+            vec![
+                symbol("main", 0x1010, 0x20),
+                symbol("parse", 0x1040, 0x80),
+                symbol("?helper@@YAXXZ", 0x1100, 0),
+            ]
+        );
+        assert!(missing.unwrap_err().contains("isn't in the symbol stores"));
+        assert_eq!(matching(&found.functions, "").count(), 3);
+        assert_eq!(matching(&found.functions, "a").count(), 2);
+        assert_eq!(matching(&found.functions, "Main").count(), 0);
+    }
+}
@@ -0,0 +1,450 @@
+// Local symbol stores in the symstore layout, the one symsrv and the debuggers use:
+//   <store>/<name.pdb>/<GUID><AGE>/<name.pdb>
+// GUID is the 32 hex digits of the PDB's GUID and AGE its age in hex, both upper case. They are
+// taken from the CodeView record of the module, so a PDB only matches the build it came from.
+
+use crate::cmdline::split_command_line;
+use crate::settings::Settings;
+use std::path::{Path, PathBuf};
+
+/// What identifies a PDB, both in a module and in the PDB itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdbId {
+    pub name: String,   // file name, e.g. cmd.pdb
+    pub guid: [u8; 16], // in the CodeView byte order
+    pub age: u32,
+}
+
+impl PdbId {
+    /// `<GUID><AGE>` dir name of the store.
+    pub fn key(&self) -> String {
+        let g = &self.guid;
+        let mut key = format!(
+            "{:08X}{:04X}{:04X}",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]])
+        );
+        for b in &g[8..] {
+            key.push_str(&format!("{:02X}", b));
+        }
+        key.push_str(&format!("{:X}", self.age));
+        key
+    }
+
+    /// Read from the PDB info and debug info streams.
+    pub fn of_pdb(path: &Path) -> Result<Self, String> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+        let mut pdb =
+            pdb::PDB::open(file).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let info = pdb
+            .pdb_information()
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        // the info stream age is bumped on every write, the module has the debug info one
+        let age = pdb
+            .debug_information()
+            .ok()
+            .and_then(|dbi| dbi.age())
+            .unwrap_or(info.age);
+        let (d1, d2, d3, d4) = info.guid.as_fields();
+        let mut guid = [0u8; 16];
+        guid[..4].copy_from_slice(&d1.to_le_bytes());
+        guid[4..6].copy_from_slice(&d2.to_le_bytes());
+        guid[6..8].copy_from_slice(&d3.to_le_bytes());
+        guid[8..].copy_from_slice(d4);
+        Ok(Self {
+            name: file_name(path),
+            guid,
+            age,
+        })
+    }
+
+    /// Read from the CodeView record of a PE module.
+    pub fn of_module(path: &Path) -> Result<Self, String> {
+        let data =
+            std::fs::read(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let file = object::File::parse(&*data)
+            .map_err(|e| format!("can't parse {}: {}", path.display(), e))?;
+        let codeview = object::Object::pdb_info(&file)
+            .map_err(|e| {
+                format!(
+                    "can't read the debug directory of {}: {}",
+                    path.display(),
+                    e
+                )
+            })?
+            .ok_or_else(|| format!("{} has no PDB reference", path.display()))?;
+        // the path is the one the linker wrote, only its name is used
+        let pdb_path = String::from_utf8_lossy(codeview.path()).into_owned();
+        let name = pdb_path
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        Ok(Self {
+            name,
+            guid: codeview.guid(),
+            age: codeview.age(),
+        })
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredPdb {
+    pub name: String,
+    pub key: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// PDBs present in `store`, sorted by name and key. Stores also keep the binaries and index files,
+/// they aren't listed.
+pub fn list(store: &Path) -> Result<Vec<StoredPdb>, String> {
+    let mut pdbs = Vec::new();
+    let names =
+        std::fs::read_dir(store).map_err(|e| format!("can't read {}: {}", store.display(), e))?;
+    for name_dir in names.flatten() {
+        let name = name_dir.file_name().to_string_lossy().into_owned();
+        if !is_pdb(Path::new(&name)) {
+            continue;
+        }
+        let Ok(keys) = std::fs::read_dir(name_dir.path()) else {
+            continue;
+        };
+        for key_dir in keys.flatten() {
+            let path = key_dir.path().join(&name);
+            if let Ok(meta) = std::fs::metadata(&path) {
+                if meta.is_file() {
+                    pdbs.push(StoredPdb {
+                        name: name.clone(),
+                        key: key_dir.file_name().to_string_lossy().into_owned(),
+                        path,
+                        size: meta.len(),
+                    });
+                }
+            }
+        }
+    }
+    pdbs.sort_by(|a, b| (&a.name, &a.key).cmp(&(&b.name, &b.key)));
+    Ok(pdbs)
+}
+
+/// Looks for the PDB in each of `stores`, in the symstore layout or directly in the dir.
+pub fn find(stores: &[String], id: &PdbId) -> Option<PathBuf> {
+    for store in stores {
+        let store = Path::new(store);
+        let path = store.join(&id.name).join(id.key()).join(&id.name);
+        if path.is_file() {
+            return Some(path);
+        }
+        // a build output dir, the PDB has to be the right one
+        let path = store.join(&id.name);
+        if path.is_file() && PdbId::of_pdb(&path).map_or(false, |found| found == *id) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Copies `pdb` into `store`, returns where it's put. A PDB already in its place is left as is.
+pub fn import(store: &Path, pdb: &Path) -> Result<PathBuf, String> {
+    let id = PdbId::of_pdb(pdb)?;
+    let dir = store.join(&id.name).join(id.key());
+    std::fs::create_dir_all(&dir).map_err(|e| format!("can't create {}: {}", dir.display(), e))?;
+    let dest = dir.join(&id.name);
+    // copying a file onto itself truncates it
+    if is_same_file(pdb, &dest) {
+        return Ok(dest);
+    }
+    std::fs::copy(pdb, &dest)
+        .map_err(|e| format!("can't copy {} to {}: {}", pdb.display(), dest.display(), e))?;
+    Ok(dest)
+}
+
+/// Imports every PDB found in `dir` and its subdirs, returns the imported ones and the errors.
+pub fn import_dir(store: &Path, dir: &Path) -> (Vec<PathBuf>, Vec<String>) {
+    let mut imported = Vec::new();
+    let mut errors = Vec::new();
+    // the store may be given by another path than the walk finds it by, a new one is created
+    // first so there's a path to compare
+    let store_dir = match std::fs::create_dir_all(store).and_then(|()| store.canonicalize()) {
+        Ok(store_dir) => store_dir,
+        Err(e) => {
+            errors.push(format!("can't create {}: {}", store.display(), e));
+            return (imported, errors);
+        }
+    };
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push(format!("can't read {}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                // the store itself may be inside of `dir`
+                if path.canonicalize().map_or(false, |p| p == store_dir) {
+                    continue;
+                }
+                dirs.push(path);
+            } else if is_pdb(&path) {
+                match import(store, &path) {
+                    Ok(dest) => imported.push(dest),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+    }
+    (imported, errors)
+}
+
+fn is_pdb(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("pdb"))
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Finds the file of the instrumented module: a path as is, the target executable itself, or a
+/// module next to it, in `PATH` or in the library dirs.
+pub fn locate_module(settings: &Settings) -> Result<PathBuf, String> {
+    let module = Path::new(&settings.inst_module);
+    if module.is_file() {
+        return Ok(module.to_owned());
+    }
+    let target = split_command_line(&settings.cmd)
+        .and_then(|args| args.into_iter().next())
+        .and_then(|program| find_program(&program));
+    let mut dirs = Vec::new();
+    if let Some(target) = &target {
+        if target
+            .file_name()
+            .map_or(false, |name| name.eq_ignore_ascii_case(module.as_os_str()))
+        {
+            return Ok(target.clone());
+        }
+        dirs.extend(target.parent().map(Path::to_owned));
+    }
+    if let Some(path) = std::env::var_os("PATH") {
+        dirs.extend(std::env::split_paths(&path));
+    }
+    if !cfg!(windows) {
+        dirs.extend(library_dirs());
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join(module))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("can't find module {:?}", settings.inst_module))
+}
+
+/// Where the dynamic linker looks for shared objects, without parsing ld.so.conf.
+fn library_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(path) = std::env::var_os("LD_LIBRARY_PATH") {
+        dirs.extend(std::env::split_paths(&path));
+    }
+    let multiarch = format!("{}-linux-gnu", std::env::consts::ARCH);
+    for dir in ["/lib", "/usr/lib", "/usr/local/lib"] {
+        dirs.push(Path::new(dir).join(&multiarch));
+        dirs.push(PathBuf::from(format!("{}64", dir)));
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+}
+
+fn find_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_owned());
+    }
+    let dirs = std::env::var_os("PATH")?;
+    std::env::split_paths(&dirs).find_map(|dir| {
+        let path = dir.join(program);
+        if path.is_file() {
+            return Some(path);
+        }
+        let exe = dir.join(format!("{}.exe", program));
+        exe.is_file().then_some(exe)
+    })
+}
+
+pub struct ModulePdb {
+    pub module: PathBuf,
+    pub id: PdbId,
+    pub found: Option<PathBuf>, // `None` if it's missing from the stores
+}
+
+/// The configured module's PDB and where it is in `stores`.
+pub fn module_pdb(settings: &Settings, stores: &[String]) -> Result<ModulePdb, String> {
+    let module = locate_module(settings)?;
+    let id = PdbId::of_module(&module)?;
+    let found = find(stores, &id);
+    Ok(ModulePdb { module, id, found })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files;
+
+    static GUID: [u8; 16] = [
+        0x78, 0x56, 0x34, 0x12, 0xbc, 0x9a, 0xf0, 0xde, 1, 2, 3, 4, 5, 6, 7, 8,
+    ];
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("symstore_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn store_inside_imported_dir() {
+        let dir = temp_dir("self");
+        std::fs::write(dir.join("app.pdb"), test_files::pdb(&GUID, 2)).unwrap();
+        let store = dir.join("store");
+        let (first, _) = import_dir(&store, &dir);
+        // the same store by another path, and a PDB imported onto itself
+        let other_path = dir.join("none").join("..").join("store");
+        std::fs::create_dir_all(dir.join("none")).unwrap();
+        let (second, errors) = import_dir(&other_path, &dir);
+        let again = import(&store, &first[0]);
+        let size = std::fs::metadata(&first[0]).map(|m| m.len());
+        let listed = list(&store);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(again.unwrap(), first[0]);
+        assert_eq!(size.unwrap(), test_files::pdb(&GUID, 2).len() as u64);
+        assert_eq!(listed.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn new_store_inside_imported_dir() {
+        let dir = temp_dir("new");
+        std::fs::write(dir.join("app.pdb"), test_files::pdb(&GUID, 2)).unwrap();
+        // the walk gets to `sub` after the store is created by the first import
+        std::fs::create_dir_all(dir.join("sub").join("none")).unwrap();
+        let store = dir.join("sub").join("none").join("..").join("store");
+        let (imported, errors) = import_dir(&store, &dir);
+        let bad_store = import_dir(&dir.join("app.pdb").join("store"), &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(imported.len(), 1);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(bad_store.0.is_empty());
+        assert!(bad_store.1[0].starts_with("can't create"));
+    }
+
+    #[test]
+    fn key_format() {
+        let id = PdbId {
+            name: "app.pdb".to_owned(),
+            guid: GUID,
+            age: 0x1a,
+        };
+        assert_eq!(id.key(), "123456789ABCDEF001020304050607081A");
+    }
+
+    #[test]
+    fn import_and_find() {
+        let dir = temp_dir("import");
+        let build = dir.join("build").join("x64");
+        std::fs::create_dir_all(&build).unwrap();
+        std::fs::write(build.join("app.pdb"), test_files::pdb(&GUID, 2)).unwrap();
+        std::fs::write(
+            build.join("app.exe"),
+            test_files::pe(&GUID, 2, "C:\\src\\app.pdb"),
+        )
+        .unwrap();
+        std::fs::write(build.join("broken.pdb"), b"not a pdb").unwrap();
+        let store = dir.join("store");
+
+        let (imported, errors) = import_dir(&store, &dir.join("build"));
+        // what symstore.exe and symsrv caches keep next to the PDBs
+        let binary = store.join("app.exe").join("5F5E10001000");
+        std::fs::create_dir_all(&binary).unwrap();
+        std::fs::write(binary.join("app.exe"), b"MZ").unwrap();
+        std::fs::write(store.join("index2.txt"), b"").unwrap();
+        std::fs::create_dir_all(store.join("000Admin").join("0000000001")).unwrap();
+        std::fs::write(
+            store.join("000Admin").join("0000000001").join("000Admin"),
+            b"",
+        )
+        .unwrap();
+        let listed = list(&store);
+        let module_id = PdbId::of_module(&build.join("app.exe"));
+        let stores = vec![store.display().to_string()];
+        let mut other = module_id.clone().unwrap();
+        other.age = 3;
+        let found = find(&stores, module_id.as_ref().unwrap());
+        let missing = find(&stores, &other);
+        // a plain dir with the matching PDB
+        let in_build = find(&[build.display().to_string()], module_id.as_ref().unwrap());
+        let not_in_build = find(&[build.display().to_string()], &other);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let expected = store
+            .join("app.pdb")
+            .join("123456789ABCDEF001020304050607082")
+            .join("app.pdb");
+        assert_eq!(imported, vec![expected.clone()]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken.pdb"));
+        let listed = listed.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].key, "123456789ABCDEF001020304050607082");
+        assert_eq!(
+            module_id.unwrap(),
+            PdbId {
+                name: "app.pdb".to_owned(),
+                guid: GUID,
+                age: 2
+            }
+        );
+        assert_eq!(found, Some(expected));
+        assert_eq!(missing, None);
+        assert_eq!(in_build, Some(build.join("app.pdb")));
+        assert_eq!(not_in_build, None);
+    }
+
+    #[test]
+    fn module_next_to_target() {
+        let dir = temp_dir("locate");
+        std::fs::write(dir.join("app"), b"").unwrap();
+        std::fs::write(dir.join("lib.dll"), b"").unwrap();
+        let mut settings = Settings {
+            inst_module: "lib.dll".to_owned(),
+            cmd: format!("\"{}\" -x", dir.join("app").display()),
+            ..Settings::default()
+        };
+        let lib = locate_module(&settings);
+        settings.inst_module = "app".to_owned();
+        let app = locate_module(&settings);
+        settings.inst_module = "missing.dll".to_owned();
+        let missing = locate_module(&settings);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(lib.unwrap(), dir.join("lib.dll"));
+        assert_eq!(app.unwrap(), dir.join("app"));
+        assert!(missing.is_err());
+    }
+}
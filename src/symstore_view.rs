@@ -0,0 +1,217 @@
+// Window listing the PDBs of a local symbol store, importing PDBs into it and checking that the
+// instrumented module's PDB is there.
+
+use crate::settings::Settings;
+use crate::symstore::{self, ModulePdb, StoredPdb};
+use eframe::egui;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+/// Result of a reload, the store may be big and the module's PDB on a network share.
+struct Reloaded {
+    pdbs: Result<Vec<StoredPdb>, String>,
+    module: Result<ModulePdb, String>,
+}
+
+struct Imported {
+    dir: PathBuf,
+    store: String,
+    imported: Vec<PathBuf>,
+    errors: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct SymstoreView {
+    pub open: bool,
+    pub is_stale: bool, // reload before showing
+    store: String,
+    pdbs: Vec<StoredPdb>,
+    module: Option<Result<ModulePdb, String>>,
+    reload_rc: Option<mpsc::Receiver<Reloaded>>,
+    import_rc: Option<mpsc::Receiver<Imported>>,
+}
+
+impl SymstoreView {
+    /// `stores` are the local stores of the symbol path, the one shown is picked from them.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        settings: &Settings,
+        stores: &[String],
+        log_text: &mut String,
+    ) {
+        // an import goes on when the window is closed
+        self.poll(log_text);
+        if !self.open {
+            return;
+        }
+        if !stores.contains(&self.store) {
+            self.store = stores.first().cloned().unwrap_or_default();
+            self.is_stale = true;
+        }
+        let is_busy = self.reload_rc.is_some() || self.import_rc.is_some();
+        if !is_busy && std::mem::take(&mut self.is_stale) {
+            self.start_reload(ctx, settings, stores);
+        }
+        let mut open = self.open;
+        egui::Window::new("Symbol store")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if stores.is_empty() {
+                    ui.label("No local stores in the symbol path");
+                    return;
+                }
+                ui.horizontal(|ui| {
+                    let mut selected = self.store.clone();
+                    egui::ComboBox::from_id_source("symstore")
+                        .selected_text(&selected)
+                        .show_ui(ui, |ui| {
+                            for store in stores {
+                                ui.selectable_value(&mut selected, store.clone(), store);
+                            }
+                        });
+                    if selected != self.store {
+                        self.store = selected;
+                        self.is_stale = true;
+                    }
+                    if ui
+                        .add_enabled(!is_busy, egui::Button::new("🔄"))
+                        .on_hover_text("Reload the store and check the module again")
+                        .clicked()
+                    {
+                        self.is_stale = true;
+                    }
+                    if ui
+                        .add_enabled(!is_busy, egui::Button::new("📁 Import"))
+                        .on_hover_text(
+                            "Copy every PDB of a folder and its subfolders into the store",
+                        )
+                        .clicked()
+                    {
+                        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            self.start_import(ctx, dir, log_text);
+                        }
+                    }
+                    if is_busy {
+                        ui.spinner();
+                    }
+                });
+                self.show_module(ui);
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("symstore_grid")
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for pdb in &self.pdbs {
+                                    ui.label(&pdb.name)
+                                        .on_hover_text(pdb.path.display().to_string());
+                                    ui.monospace(&pdb.key);
+                                    ui.label(format!("{:.1} MB", pdb.size as f64 / 1e6));
+                                    ui.end_row();
+                                }
+                            });
+                        if self.pdbs.is_empty() {
+                            ui.label("The store is empty");
+                        }
+                    });
+            });
+        self.open = open;
+    }
+
+    fn show_module(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Module");
+            match &self.module {
+                Some(Ok(pdb)) => {
+                    ui.label(format!("{} {}", pdb.id.name, pdb.id.key()))
+                        .on_hover_text(pdb.module.display().to_string());
+                    match &pdb.found {
+                        Some(found) => ui.label(format!("found: {}", found.display())),
+                        None => {
+                            ui.colored_label(egui::Color32::RED, "☹ missing from the symbol stores")
+                        }
+                    };
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(egui::Color32::RED, "☹").on_hover_text(e);
+                }
+                None => {}
+            }
+        });
+    }
+
+    fn start_reload(&mut self, ctx: &egui::Context, settings: &Settings, stores: &[String]) {
+        let (tx, rc) = mpsc::channel();
+        let store = self.store.clone();
+        let settings = settings.clone();
+        let stores = stores.to_vec();
+        let ctx2 = ctx.clone();
+        std::thread::spawn(move || {
+            let pdbs = if Path::new(&store).is_dir() {
+                symstore::list(Path::new(&store))
+            } else {
+                Ok(Vec::new())
+            };
+            let module = symstore::module_pdb(&settings, &stores);
+            let _ = tx.send(Reloaded { pdbs, module });
+            ctx2.request_repaint();
+        });
+        self.reload_rc = Some(rc);
+    }
+
+    fn start_import(&mut self, ctx: &egui::Context, dir: PathBuf, log_text: &mut String) {
+        log_text.push_str(
+            format!(
+                "Importing PDBs from {} into {} ...\n",
+                dir.display(),
+                self.store
+            )
+            .as_str(),
+        );
+        let (tx, rc) = mpsc::channel();
+        let store = self.store.clone();
+        let ctx2 = ctx.clone();
+        std::thread::spawn(move || {
+            let (imported, errors) = symstore::import_dir(Path::new(&store), &dir);
+            let _ = tx.send(Imported {
+                dir,
+                store,
+                imported,
+                errors,
+            });
+            ctx2.request_repaint();
+        });
+        self.import_rc = Some(rc);
+    }
+
+    /// Takes the results of the background reload and import.
+    fn poll(&mut self, log_text: &mut String) {
+        if let Some(Ok(reloaded)) = self.reload_rc.as_ref().map(|rc| rc.try_recv()) {
+            self.reload_rc = None;
+            self.pdbs = reloaded.pdbs.unwrap_or_else(|e| {
+                log_text.push_str(format!("Error: {}\n", e).as_str());
+                Vec::new()
+            });
+            self.module = Some(reloaded.module);
+        }
+        if let Some(Ok(imported)) = self.import_rc.as_ref().map(|rc| rc.try_recv()) {
+            self.import_rc = None;
+            for e in &imported.errors {
+                log_text.push_str(format!("Error: {}\n", e).as_str());
+            }
+            log_text.push_str(
+                format!(
+                    "Imported {} PDBs from {} into {}\n",
+                    imported.imported.len(),
+                    imported.dir.display(),
+                    imported.store
+                )
+                .as_str(),
+            );
+            self.is_stale = true;
+        }
+    }
+}
@@ -0,0 +1,386 @@
+// Minimal PE and PDB files for tests, just enough for the parsers to accept them.
+
+/// PDB stream data, `None` for a missing stream.
+pub fn msf(streams: &[Option<Vec<u8>>]) -> Vec<u8> {
+    const PAGE: usize = 4096;
+    let pages = |len: usize| (len + PAGE - 1) / PAGE;
+    // 0 header, 1 and 2 free page maps, 3 list of the directory pages, then streams and directory
+    let mut next_page = 4;
+    let mut directory = vec![streams.len() as u32];
+    for stream in streams {
+        directory.push(stream.as_ref().map_or(u32::MAX, |s| s.len() as u32));
+    }
+    let mut stream_pages = Vec::new();
+    for stream in streams.iter().flatten() {
+        for _ in 0..pages(stream.len()) {
+            directory.push(next_page as u32);
+            stream_pages.push(next_page);
+            next_page += 1;
+        }
+    }
+    let directory: Vec<u8> = directory.iter().flat_map(|n| n.to_le_bytes()).collect();
+    let directory_page = next_page;
+    let total_pages = directory_page + pages(directory.len());
+
+    let mut file = vec![0u8; total_pages * PAGE];
+    let mut header = b"Microsoft C/C++ MSF 7.00\r\n\x1a\x44\x53\x00\x00\x00".to_vec();
+    for n in [PAGE, 1, total_pages, directory.len(), 0, 3] {
+        header.extend((n as u32).to_le_bytes());
+    }
+    file[..header.len()].copy_from_slice(&header);
+    for i in 0..pages(directory.len()) {
+        let at = 3 * PAGE + i * 4;
+        file[at..at + 4].copy_from_slice(&((directory_page + i) as u32).to_le_bytes());
+    }
+    file[directory_page * PAGE..][..directory.len()].copy_from_slice(&directory);
+    let mut page = stream_pages.into_iter();
+    for stream in streams.iter().flatten() {
+        for chunk in stream.chunks(PAGE) {
+            let at = page.next().unwrap() * PAGE;
+            file[at..at + chunk.len()].copy_from_slice(chunk);
+        }
+    }
+    file
+}
+
+/// PDB info stream, `guid` in its on-disk byte order.
+pub fn pdb_info_stream(guid: &[u8; 16], age: u32) -> Vec<u8> {
+    let mut stream = Vec::new();
+    for n in [20000404, 0x5f00_0000, age] {
+        stream.extend(u32::to_le_bytes(n));
+    }
+    stream.extend(guid);
+    // no named streams: names size, then an empty hash table and its two bit sets
+    stream.extend([0u8; 4 * 5]);
+    stream
+}
+
+pub fn pdb(guid: &[u8; 16], age: u32) -> Vec<u8> {
+    msf(&[Some(Vec::new()), Some(pdb_info_stream(guid, age))])
+}
+
+/// x64 PE with a single section holding a CodeView debug entry.
+pub fn pe(guid: &[u8; 16], age: u32, pdb_path: &str) -> Vec<u8> {
+    let mut file = vec![0u8; 0x400];
+    let mut put = |at: usize, bytes: &[u8]| file[at..at + bytes.len()].copy_from_slice(bytes);
+    put(0, b"MZ");
+    put(0x3c, &0x40u32.to_le_bytes());
+    put(0x40, b"PE\0\0");
+    // file header: machine, sections, optional header size, characteristics
+    put(0x44, &0x8664u16.to_le_bytes());
+    put(0x46, &1u16.to_le_bytes());
+    put(0x54, &240u16.to_le_bytes());
+    put(0x56, &0x22u16.to_le_bytes());
+    // PE32+ optional header
+    let opt = 0x58;
+    put(opt, &0x20bu16.to_le_bytes());
+    put(opt + 24, &0x1_4000_0000u64.to_le_bytes()); // image base
+    put(opt + 32, &0x1000u32.to_le_bytes()); // section alignment
+    put(opt + 36, &0x200u32.to_le_bytes()); // file alignment
+    put(opt + 56, &0x2000u32.to_le_bytes()); // image size
+    put(opt + 60, &0x200u32.to_le_bytes()); // headers size
+    put(opt + 68, &3u16.to_le_bytes()); // console subsystem
+    put(opt + 108, &16u32.to_le_bytes()); // data directories
+    put(opt + 112 + 6 * 8, &0x1000u32.to_le_bytes()); // debug directory
+    put(opt + 112 + 6 * 8 + 4, &28u32.to_le_bytes());
+    // .rdata section header
+    let section = opt + 240;
+    put(section, b".rdata\0\0");
+    put(section + 8, &0x200u32.to_le_bytes());
+    put(section + 12, &0x1000u32.to_le_bytes());
+    put(section + 16, &0x200u32.to_le_bytes());
+    put(section + 20, &0x200u32.to_le_bytes());
+    put(section + 36, &0x4000_0040u32.to_le_bytes());
+    // debug directory entry, CodeView data right after it
+    let mut codeview = b"RSDS".to_vec();
+    codeview.extend(guid);
+    codeview.extend(age.to_le_bytes());
+    codeview.extend(pdb_path.as_bytes());
+    codeview.push(0);
+    put(0x200 + 12, &2u32.to_le_bytes());
+    put(0x200 + 16, &(codeview.len() as u32).to_le_bytes());
+    put(0x200 + 20, &(0x1000u32 + 28).to_le_bytes());
+    put(0x200 + 24, &(0x200u32 + 28).to_le_bytes());
+    put(0x200 + 28, &codeview);
+    file
+}
+
+/// `name` at `rva` in the single .text section at 0x1000, `len` 0 for a public symbol.
+pub struct Function<'a> {
+    pub name: &'a str,
+    pub rva: u32,
+    pub len: u32,
+}
+
+/// Symbol record: length, kind, data padded to 4 bytes.
+fn symbol(kind: u16, data: &[u8]) -> Vec<u8> {
+    let mut data = data.to_vec();
+    while (data.len() + 4) % 4 != 0 {
+        data.push(0);
+    }
+    let mut record = ((data.len() + 2) as u16).to_le_bytes().to_vec();
+    record.extend(kind.to_le_bytes());
+    record.extend(data);
+    record
+}
+
+/// PDB with public symbols (S_PUB32) for `len` 0 functions and procedures (S_GPROC32) in a
+/// module for the rest.
+pub fn pdb_with_functions(guid: &[u8; 16], age: u32, functions: &[Function]) -> Vec<u8> {
+    const TEXT_RVA: u32 = 0x1000;
+    const SYMBOLS_STREAM: u16 = 5;
+    const SECTIONS_STREAM: u16 = 6;
+    const MODULE_STREAM: u16 = 7;
+    let mut publics = Vec::new();
+    let mut module = 4u32.to_le_bytes().to_vec(); // C13 signature
+    for f in functions {
+        let offset = (f.rva - TEXT_RVA).to_le_bytes();
+        if f.len == 0 {
+            let mut data = 2u32.to_le_bytes().to_vec(); // function flag
+            data.extend(offset);
+            data.extend(1u16.to_le_bytes()); // section
+            data.extend(f.name.as_bytes());
+            data.push(0);
+            publics.extend(symbol(0x110e, &data));
+        } else {
+            // parent, end, next, length, debug start and end, type
+            let mut data: Vec<u8> = [0, 0, 0, f.len, 0, f.len, 0]
+                .iter()
+                .flat_map(|n: &u32| n.to_le_bytes())
+                .collect();
+            data.extend(offset);
+            data.extend(1u16.to_le_bytes());
+            data.push(0); // flags
+            data.extend(f.name.as_bytes());
+            data.push(0);
+            module.extend(symbol(0x1110, &data));
+            module.extend(symbol(0x0006, &[])); // S_END
+        }
+    }
+
+    let mut modules = vec![0u8; 4]; // opened
+
+    // section contribution: section, offset, size, characteristics, module, crcs
+    modules.extend(1u16.to_le_bytes());
+    modules.extend([0u8; 2 + 4]);
+    modules.extend(0x1000u32.to_le_bytes());
+    modules.extend(0x6000_0020u32.to_le_bytes());
+    modules.extend([0u8; 4 + 8]);
+    modules.extend(0u16.to_le_bytes()); // flags
+    modules.extend(MODULE_STREAM.to_le_bytes());
+    modules.extend((module.len() as u32).to_le_bytes());
+    // line sizes, files, file names offset, source and compiler names
+    modules.extend([0u8; 4 + 4 + 2 + 2 + 4 + 4 + 4]);
+    modules.extend(b"a.obj\0a.obj\0");
+    while modules.len() % 4 != 0 {
+        modules.push(0);
+    }
+    // optional streams, only the section headers are there
+    let debug_header: Vec<u8> = (0..11)
+        .flat_map(|i| if i == 5 { SECTIONS_STREAM } else { u16::MAX }.to_le_bytes())
+        .collect();
+
+    let mut dbi = Vec::new();
+    dbi.extend(u32::MAX.to_le_bytes());
+    dbi.extend(19990903u32.to_le_bytes());
+    dbi.extend(age.to_le_bytes());
+    // global and public symbol hashes, symbol records, versions
+    for n in [u16::MAX, 0, u16::MAX, 0, SYMBOLS_STREAM, 0] {
+        dbi.extend(n.to_le_bytes());
+    }
+    let sizes = [modules.len(), 0, 0, 0, 0, 0, debug_header.len(), 0];
+    for n in sizes {
+        dbi.extend((n as u32).to_le_bytes());
+    }
+    dbi.extend(0u16.to_le_bytes());
+    dbi.extend(0x8664u16.to_le_bytes());
+    dbi.extend(0u32.to_le_bytes());
+    dbi.extend(modules);
+    dbi.extend(debug_header);
+
+    let mut sections = b".text\0\0\0".to_vec();
+    for n in [0x1000, TEXT_RVA, 0x1000, 0x400, 0, 0, 0] {
+        sections.extend(u32::to_le_bytes(n));
+    }
+    sections.extend(0x6000_0020u32.to_le_bytes());
+
+    msf(&[
+        Some(Vec::new()),
+        Some(pdb_info_stream(guid, age)),
+        None,
+        Some(dbi),
+        None,
+        Some(publics),
+        Some(sections),
+        Some(module),
+    ])
+}
+
+/// x86-64 shared object loaded at 0 with a .text section at 0x1000, `len` is the symbol size.
+#[derive(Default)]
+pub struct Elf<'a> {
+    pub symtab: &'a [Function<'a>],
+    pub dynsym: &'a [Function<'a>],
+    pub dwarf: &'a [Function<'a>], // subprograms of a single compile unit
+    pub build_id: Option<&'a [u8]>,
+    pub debuglink: Option<(&'a str, u32)>, // file name and CRC
+}
+
+struct ElfSection {
+    name: &'static str,
+    kind: u32,
+    flags: u64,
+    addr: u64,
+    data: Vec<u8>,
+    link: u32,
+    entsize: u64,
+}
+
+impl Elf<'_> {
+    pub fn build(&self) -> Vec<u8> {
+        const TEXT: u16 = 1;
+        let section = |name, kind, data| ElfSection {
+            name,
+            kind,
+            flags: 0,
+            addr: 0,
+            data,
+            link: 0,
+            entsize: 0,
+        };
+        let mut sections = vec![ElfSection {
+            flags: 6, // alloc, exec
+            addr: 0x1000,
+            ..section(".text", 8, Vec::new())
+        }];
+        for (name, strtab, kind, functions) in [
+            (".symtab", ".strtab", 2, self.symtab),
+            (".dynsym", ".dynstr", 11, self.dynsym),
+        ] {
+            if functions.is_empty() {
+                continue;
+            }
+            let mut strings = vec![0u8];
+            let mut symbols = vec![0u8; 24];
+            for f in functions {
+                symbols.extend((strings.len() as u32).to_le_bytes());
+                symbols.push(0x12); // global function
+                symbols.push(0);
+                symbols.extend(TEXT.to_le_bytes());
+                symbols.extend(u64::from(f.rva).to_le_bytes());
+                symbols.extend(u64::from(f.len).to_le_bytes());
+                strings.extend(f.name.as_bytes());
+                strings.push(0);
+            }
+            sections.push(ElfSection {
+                link: sections.len() as u32 + 2, // the string table after it
+                entsize: 24,
+                ..section(name, kind, symbols)
+            });
+            sections.push(section(strtab, 3, strings));
+        }
+        if let Some(id) = self.build_id {
+            let mut note = Vec::new();
+            for n in [4, id.len() as u32, 3] {
+                note.extend(n.to_le_bytes());
+            }
+            note.extend(b"GNU\0");
+            note.extend(id);
+            while note.len() % 4 != 0 {
+                note.push(0);
+            }
+            sections.push(section(".note.gnu.build-id", 7, note));
+        }
+        if let Some((name, crc)) = self.debuglink {
+            let mut link = name.as_bytes().to_vec();
+            link.push(0);
+            while link.len() % 4 != 0 {
+                link.push(0);
+            }
+            link.extend(crc.to_le_bytes());
+            sections.push(section(".gnu_debuglink", 1, link));
+        }
+        if !self.dwarf.is_empty() {
+            // 1: compile unit with children, 2: subprogram with name, low_pc and high_pc as size
+            let abbrev = vec![
+                1, 0x11, 1, 0, 0, 2, 0x2e, 0, 0x03, 0x08, 0x11, 0x01, 0x12, 0x06, 0, 0, 0,
+            ];
+            let mut dies = vec![1];
+            for f in self.dwarf {
+                dies.push(2);
+                dies.extend(f.name.as_bytes());
+                dies.push(0);
+                dies.extend(u64::from(f.rva).to_le_bytes());
+                dies.extend(f.len.to_le_bytes());
+            }
+            dies.push(0);
+            let mut info = ((dies.len() + 7) as u32).to_le_bytes().to_vec();
+            info.extend(4u16.to_le_bytes()); // version
+            info.extend(0u32.to_le_bytes()); // abbreviations offset
+            info.push(8); // address size
+            info.extend(dies);
+            sections.push(section(".debug_abbrev", 1, abbrev));
+            sections.push(section(".debug_info", 1, info));
+        }
+        let mut names = vec![0u8];
+        let mut name_offsets = Vec::new();
+        for s in sections.iter().map(|s| s.name).chain([".shstrtab"]) {
+            name_offsets.push(names.len() as u32);
+            names.extend(s.as_bytes());
+            names.push(0);
+        }
+        sections.push(section(".shstrtab", 3, names));
+
+        // header, a single load segment, section data, then the section headers
+        let mut file = vec![0u8; 64 + 56];
+        let mut offsets = Vec::new();
+        for s in &sections {
+            offsets.push(file.len() as u64);
+            file.extend(&s.data);
+            while file.len() % 8 != 0 {
+                file.push(0);
+            }
+        }
+        let headers = file.len() as u64;
+        file.extend([0u8; 64]);
+        for ((s, offset), name) in sections.iter().zip(offsets).zip(name_offsets) {
+            file.extend(name.to_le_bytes());
+            file.extend(s.kind.to_le_bytes());
+            file.extend(s.flags.to_le_bytes());
+            file.extend(s.addr.to_le_bytes());
+            let size = if s.kind == 8 {
+                0x1000
+            } else {
+                s.data.len() as u64
+            };
+            for n in [offset, size] {
+                file.extend(n.to_le_bytes());
+            }
+            file.extend(s.link.to_le_bytes());
+            // first global symbol
+            file.extend(u32::from(s.entsize != 0).to_le_bytes());
+            file.extend(1u64.to_le_bytes());
+            file.extend(s.entsize.to_le_bytes());
+        }
+        let mut put = |at: usize, bytes: &[u8]| file[at..at + bytes.len()].copy_from_slice(bytes);
+        put(0, b"\x7fELF\x02\x01\x01");
+        put(16, &3u16.to_le_bytes()); // shared object
+        put(18, &62u16.to_le_bytes()); // x86-64
+        put(20, &1u32.to_le_bytes());
+        put(32, &64u64.to_le_bytes()); // program headers
+        put(40, &headers.to_le_bytes());
+        put(52, &64u16.to_le_bytes());
+        put(54, &56u16.to_le_bytes());
+        put(56, &1u16.to_le_bytes());
+        put(58, &64u16.to_le_bytes());
+        put(60, &(sections.len() as u16 + 1).to_le_bytes());
+        put(62, &(sections.len() as u16).to_le_bytes()); // .shstrtab is the last one
+
+        // PT_LOAD, read and execute, nothing from the file, 0x3000 in memory
+        put(64, &1u32.to_le_bytes());
+        put(68, &5u32.to_le_bytes());
+        put(64 + 40, &0x3000u64.to_le_bytes());
+        put(64 + 48, &0x1000u64.to_le_bytes());
+        file
+    }
+}
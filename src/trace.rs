@@ -0,0 +1,368 @@
+// Parser for the DrSymLogger call trace:
+//
+//  -> BatLoop
+//      -> OpenPosBat
+//      <- OpenPosBat (0x0000000000000003)
+//  <- BatLoop (0x0000000000000000)
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceLine<'a> {
+    Enter(&'a str),
+    Exit(&'a str, Option<u64>),
+}
+
+/// Recognizes a single `->`/`<-` line, anything else is the target's own output.
+pub fn parse_line(line: &str) -> Option<TraceLine<'_>> {
+    let line = line.trim();
+    if let Some(name) = line.strip_prefix("-> ") {
+        let name = name.trim();
+        return (!name.is_empty()).then_some(TraceLine::Enter(name));
+    }
+    let rest = line.strip_prefix("<- ")?.trim();
+    // the return value is the last token in parentheses: "Name (0x0000000000000003)"
+    if let Some(pos) = rest.rfind(" (0x") {
+        if rest.ends_with(')') {
+            let hex = &rest[pos + 4..rest.len() - 1];
+            if let Ok(ret) = u64::from_str_radix(hex, 16) {
+                return Some(TraceLine::Exit(rest[..pos].trim_end(), Some(ret)));
+            }
+        }
+    }
+    (!rest.is_empty()).then_some(TraceLine::Exit(rest, None))
+}
+
+/// How a call ended in the trace.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallEnd {
+    /// Matching `<-` was found, the return value is missing if it couldn't be parsed.
+    Returned(Option<u64>),
+    /// No `<-` of its own, but a caller returned or a sibling was entered (longjmp, exceptions).
+    Unwound,
+    /// Still running when the trace ended.
+    Truncated,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallNode {
+    pub name: String,
+    pub depth: usize,
+    pub line: usize,              // 1-based line of the `->`
+    pub exit_line: Option<usize>, // line of the `<-`, or of whatever ended the call
+    pub end: CallEnd,
+    pub children: Vec<CallNode>,
+}
+
+impl CallNode {
+    fn new(name: &str, depth: usize, line: usize) -> Self {
+        Self {
+            name: name.to_owned(),
+            depth,
+            line,
+            exit_line: None,
+            end: CallEnd::Truncated,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// `<-` that has no open call to close, e.g. the trace started mid-run or a setjmp returning twice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrphanExit {
+    pub name: String,
+    pub ret: Option<u64>,
+    pub line: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub roots: Vec<CallNode>,
+    pub orphan_exits: Vec<OrphanExit>,
+    pub other_lines: usize, // non-trace output of the target
+    pub line_count: usize,
+}
+
+impl Trace {
+    /// Builds the call tree. DrSymLogger indents every call level, when the trace is indented the
+    /// indentation is trusted to resynchronize after unbalanced enters/exits, otherwise exits are
+    /// matched by name.
+    pub fn parse(text: &str) -> Self {
+        let use_indent = {
+            let mut indents = text
+                .lines()
+                .filter(|l| parse_line(l).is_some())
+                .map(indent_of);
+            let first = indents.next();
+            indents.any(|i| Some(i) != first)
+        };
+        let mut trace = Trace::default();
+        let mut stack: Vec<(usize, CallNode)> = Vec::new(); // (indent, call)
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            trace.line_count = line_no;
+            let indent = indent_of(line);
+            match parse_line(line) {
+                Some(TraceLine::Enter(name)) => {
+                    if use_indent {
+                        // whatever is open at the same or deeper level never returned
+                        while stack.last().map_or(false, |(i, _)| *i >= indent) {
+                            close(&mut stack, &mut trace.roots, CallEnd::Unwound, line_no);
+                        }
+                    }
+                    let depth = stack.len();
+                    stack.push((indent, CallNode::new(name, depth, line_no)));
+                }
+                Some(TraceLine::Exit(name, ret)) => {
+                    let target = if use_indent {
+                        while stack.last().map_or(false, |(i, _)| *i > indent) {
+                            close(&mut stack, &mut trace.roots, CallEnd::Unwound, line_no);
+                        }
+                        match stack.last() {
+                            Some((i, node)) if *i == indent && node.name == name => {
+                                Some(stack.len() - 1)
+                            }
+                            Some((i, _)) if *i == indent => {
+                                // a different call at this level, it's gone as well
+                                close(&mut stack, &mut trace.roots, CallEnd::Unwound, line_no);
+                                None
+                            }
+                            _ => None,
+                        }
+                    } else {
+                        stack.iter().rposition(|(_, node)| node.name == name)
+                    };
+                    match target {
+                        Some(pos) => {
+                            while stack.len() > pos + 1 {
+                                close(&mut stack, &mut trace.roots, CallEnd::Unwound, line_no);
+                            }
+                            close(
+                                &mut stack,
+                                &mut trace.roots,
+                                CallEnd::Returned(ret),
+                                line_no,
+                            );
+                        }
+                        None => trace.orphan_exits.push(OrphanExit {
+                            name: name.to_owned(),
+                            ret,
+                            line: line_no,
+                        }),
+                    }
+                }
+                None => {
+                    if !line.trim().is_empty() {
+                        trace.other_lines += 1;
+                    }
+                }
+            }
+        }
+        // the trace may end before the calls return, they stay marked as truncated
+        while let Some((_, node)) = stack.pop() {
+            attach(&mut stack, &mut trace.roots, node);
+        }
+        trace
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Ok(Self::parse(&String::from_utf8_lossy(&bytes)))
+    }
+
+    /// How many times each function was called over the whole trace.
+    pub fn call_counts(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        let mut stack: Vec<&CallNode> = self.roots.iter().collect();
+        while let Some(node) = stack.pop() {
+            *counts.entry(node.name.clone()).or_insert(0) += 1;
+            stack.extend(node.children.iter());
+        }
+        counts
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Pops the innermost open call, marks how it ended and hangs it under its caller.
+fn close(stack: &mut Vec<(usize, CallNode)>, roots: &mut Vec<CallNode>, end: CallEnd, line: usize) {
+    if let Some((_, mut node)) = stack.pop() {
+        node.end = end;
+        node.exit_line = Some(line);
+        attach(stack, roots, node);
+    }
+}
+
+fn attach(stack: &mut [(usize, CallNode)], roots: &mut Vec<CallNode>, node: CallNode) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent.children.push(node),
+        None => roots.push(node),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            parse_line("     -> BatLoop"),
+            Some(TraceLine::Enter("BatLoop"))
+        );
+        assert_eq!(
+            parse_line(" <- OpenPosBat (0x0000000000000003)"),
+            Some(TraceLine::Exit("OpenPosBat", Some(3)))
+        );
+        assert_eq!(parse_line("Hello! What's your name?"), None);
+    }
+
+    #[test]
+    fn tree() {
+        let trace = Trace::parse(
+            " -> BatLoop
+     -> OpenPosBat
+     <- OpenPosBat (0x0000000000000003)
+     -> Parser
+     <- Parser (0x0000000000000000)
+ <- BatLoop (0x0000000000000001)
+",
+        );
+        assert_eq!(trace.roots.len(), 1);
+        let root = &trace.roots[0];
+        assert_eq!(root.name, "BatLoop");
+        assert_eq!(ret(root), Some(1));
+        assert_eq!(root.exit_line, Some(6));
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[0].name, "OpenPosBat");
+        assert_eq!(root.children[0].depth, 1);
+        assert_eq!(root.children[1].line, 4);
+        assert_eq!(trace.call_counts()["Parser"], 1);
+    }
+
+    fn ret(node: &CallNode) -> Option<u64> {
+        match node.end {
+            CallEnd::Returned(ret) => ret,
+            _ => None,
+        }
+    }
+
+    fn names(nodes: &[CallNode]) -> Vec<&str> {
+        nodes.iter().map(|n| n.name.as_str()).collect()
+    }
+
+    #[test]
+    fn fixture_balanced() {
+        let trace = Trace::parse(include_str!("../tests/fixtures/balanced.txt"));
+        assert_eq!(names(&trace.roots), vec!["BatLoop"]);
+        assert!(trace.orphan_exits.is_empty());
+        assert_eq!(trace.other_lines, 0);
+        let parser = &trace.roots[0].children[1];
+        assert_eq!(
+            names(&parser.children),
+            vec!["_intrinsic_setjmp", "GeToken"]
+        );
+        let fill_buf = &parser.children[1].children[0].children[0];
+        assert_eq!(fill_buf.name, "FillBuf");
+        assert_eq!(fill_buf.depth, 4);
+        assert_eq!(fill_buf.end, CallEnd::Returned(Some(0x7ff627e949f2)));
+        assert_eq!(names(&fill_buf.children), vec!["ResetCtrlC", "ResetCtrlC"]);
+        assert_eq!(trace.call_counts()["ResetCtrlC"], 2);
+    }
+
+    #[test]
+    fn fixture_truncated() {
+        let trace = Trace::parse(include_str!("../tests/fixtures/truncated.txt"));
+        assert_eq!(names(&trace.roots), vec!["BatLoop"]);
+        let parser = &trace.roots[0].children[0];
+        assert_eq!(parser.end, CallEnd::Truncated);
+        assert_eq!(parser.exit_line, None);
+        assert_eq!(parser.children[0].end, CallEnd::Returned(Some(0x4000)));
+        // the last line is cut in the middle of a name, it's still an enter
+        let mut node = &parser.children[1];
+        while let Some(child) = node.children.first() {
+            node = child;
+        }
+        assert_eq!(node.name, "Binary");
+        assert_eq!(node.depth, 7);
+        assert_eq!(node.end, CallEnd::Truncated);
+    }
+
+    #[test]
+    fn fixture_longjmp() {
+        let trace = Trace::parse(include_str!("../tests/fixtures/longjmp.txt"));
+        assert_eq!(names(&trace.roots), vec!["Parser"]);
+        let parser = &trace.roots[0];
+        assert_eq!(ret(parser), Some(1));
+        // the second return of setjmp doesn't close anything, but unwinds ParseS0 and below
+        assert_eq!(
+            trace.orphan_exits,
+            vec![OrphanExit {
+                name: "_intrinsic_setjmp".to_owned(),
+                ret: Some(1),
+                line: 9
+            }]
+        );
+        assert_eq!(
+            names(&parser.children),
+            vec!["_intrinsic_setjmp", "ParseS0", "FreeStack"]
+        );
+        let parse_s0 = &parser.children[1];
+        assert_eq!(parse_s0.end, CallEnd::Unwound);
+        assert_eq!(parse_s0.exit_line, Some(9));
+        let longjmp = &parse_s0.children[0].children[0].children[0].children[0];
+        assert_eq!(longjmp.name, "longjmp");
+        assert_eq!(longjmp.end, CallEnd::Unwound);
+        assert_eq!(parser.children[2].depth, 1);
+    }
+
+    #[test]
+    fn fixture_unwound() {
+        let trace = Trace::parse(include_str!("../tests/fixtures/unwound.txt"));
+        let bat_loop = &trace.roots[0];
+        assert_eq!(names(&bat_loop.children), vec!["Parser", "Dispatch"]);
+        let parser = &bat_loop.children[0];
+        assert_eq!(ret(parser), Some(0));
+        assert_eq!(parser.children[0].end, CallEnd::Unwound);
+        assert_eq!(parser.children[0].children[0].end, CallEnd::Unwound);
+        assert!(trace.orphan_exits.is_empty());
+    }
+
+    #[test]
+    fn fixture_mid_run() {
+        let trace = Trace::parse(include_str!("../tests/fixtures/mid_run.txt"));
+        assert_eq!(names(&trace.roots), vec!["ParseS0", "BatLoop"]);
+        assert_eq!(
+            trace
+                .orphan_exits
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Lex", "GeToken", "Parser"]
+        );
+        assert_eq!(trace.roots[1].depth, 0);
+    }
+
+    #[test]
+    fn fixture_interleaved() {
+        let trace = Trace::parse(include_str!("../tests/fixtures/interleaved.txt"));
+        assert_eq!(names(&trace.roots), vec!["main"]);
+        assert_eq!(ret(&trace.roots[0]), Some(123));
+        assert_eq!(names(&trace.roots[0].children), vec!["GetName"]);
+        assert_eq!(ret(&trace.roots[0].children[0]), Some(10));
+        assert_eq!(trace.other_lines, 2);
+    }
+
+    #[test]
+    fn unindented_exits_match_by_name() {
+        let trace = Trace::parse("-> a\n-> b\n-> c\n<- a (0x1)\n-> d\n<- x (0x2)\n<- d (0x3)\n");
+        assert_eq!(names(&trace.roots), vec!["a", "d"]);
+        let b = &trace.roots[0].children[0];
+        assert_eq!(b.end, CallEnd::Unwound);
+        assert_eq!(b.children[0].end, CallEnd::Unwound);
+        assert_eq!(trace.orphan_exits.len(), 1);
+        assert_eq!(ret(&trace.roots[1]), Some(3));
+    }
+}
@@ -0,0 +1,99 @@
+// Collapsible call tree window for the trace written to the redirect file.
+
+use crate::trace::{CallEnd, CallNode, Trace};
+use eframe::egui;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct TraceView {
+    pub open: bool,
+    path: String,
+    trace: Trace,
+    total_calls: HashMap<String, usize>,  // by function name
+    subtree_calls: HashMap<usize, usize>, // by node line
+}
+
+impl TraceView {
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let trace = Trace::load(path).map_err(|e| format!("can't read {:?}: {}", path, e))?;
+        self.total_calls = trace.call_counts();
+        self.subtree_calls = subtree_calls(&trace);
+        self.trace = trace;
+        self.path = path.to_owned();
+        self.open = true;
+        Ok(())
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Call trace")
+            .open(&mut open)
+            .default_size(egui::vec2(600.0, 400.0))
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{}: {} top level calls, {} functions, {} unmatched returns, {} other lines",
+                    self.path,
+                    self.trace.roots.len(),
+                    self.total_calls.len(),
+                    self.trace.orphan_exits.len(),
+                    self.trace.other_lines
+                ));
+                ui.separator();
+                egui::ScrollArea::both().show(ui, |ui| {
+                    for node in &self.trace.roots {
+                        self.show_node(ui, node);
+                    }
+                });
+            });
+        self.open = open;
+    }
+
+    fn show_node(&self, ui: &mut egui::Ui, node: &CallNode) {
+        let ret = match node.end {
+            CallEnd::Returned(Some(ret)) => format!("0x{:x}", ret),
+            CallEnd::Returned(None) => "?".to_owned(),
+            CallEnd::Unwound => "unwound".to_owned(),
+            CallEnd::Truncated => "no return".to_owned(),
+        };
+        let text = format!("{} = {}", node.name, ret);
+        let hover = format!(
+            "line {}, depth {}\n{} calls in this subtree\n{} calls of {} in the trace",
+            node.line,
+            node.depth,
+            self.subtree_calls.get(&node.line).copied().unwrap_or(1),
+            self.total_calls.get(&node.name).copied().unwrap_or(0),
+            node.name
+        );
+        if node.children.is_empty() {
+            ui.label(egui::RichText::new(text).monospace())
+                .on_hover_text(hover);
+            return;
+        }
+        egui::CollapsingHeader::new(egui::RichText::new(text).monospace())
+            .id_source(node.line)
+            .show(ui, |ui| {
+                for child in &node.children {
+                    self.show_node(ui, child);
+                }
+            })
+            .header_response
+            .on_hover_text(hover);
+    }
+}
+
+/// Call counts for every node, computed once so the window doesn't walk subtrees every frame.
+fn subtree_calls(trace: &Trace) -> HashMap<usize, usize> {
+    // pre-order walk, then children are summed up before their parents in reverse
+    let mut order = Vec::new();
+    let mut stack: Vec<&CallNode> = trace.roots.iter().collect();
+    while let Some(node) = stack.pop() {
+        order.push(node);
+        stack.extend(node.children.iter());
+    }
+    let mut counts = HashMap::new();
+    for node in order.into_iter().rev() {
+        let children: usize = node.children.iter().map(|c| counts[&c.line]).sum();
+        counts.insert(node.line, children + 1);
+    }
+    counts
+}